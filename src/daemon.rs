@@ -0,0 +1,166 @@
+use crate::{
+    build_blocklist_with_metrics,
+    file_system::{get_config_from_file, write_blocklist_files, Config, Daemon, HttpOverrides},
+    hooks::run_post_write_hooks,
+    http_server::spawn_metrics_endpoint,
+    metrics::BuildMetrics,
+    zone_server::publish_zone,
+};
+use log::{error, info};
+use num_format::{Locale, ToFormattedString};
+use rand::Rng;
+use std::{
+    path::Path,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+fn next_delay(daemon: &Daemon) -> Duration {
+    let Daemon { interval, jitter } = daemon;
+    let jitter_millis = u64::try_from(jitter.as_millis()).unwrap_or(u64::MAX);
+    let extra = rand::thread_rng().gen_range(0..=jitter_millis);
+    *interval + Duration::from_millis(extra)
+}
+
+#[cfg(unix)]
+type ReloadSignal = tokio::signal::unix::Signal;
+
+#[cfg(unix)]
+fn reload_signal() -> std::io::Result<ReloadSignal> {
+    tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
+}
+
+#[cfg(unix)]
+async fn reload_requested(signal: &mut ReloadSignal) {
+    signal.recv().await;
+}
+
+#[cfg(not(unix))]
+type ReloadSignal = ();
+
+#[cfg(not(unix))]
+#[allow(clippy::unnecessary_wraps)]
+fn reload_signal() -> std::io::Result<ReloadSignal> {
+    Ok(())
+}
+
+#[cfg(not(unix))]
+async fn reload_requested(_signal: &mut ReloadSignal) {
+    std::future::pending::<()>().await;
+}
+
+/// Rebuilds the blocklist on the configured interval.  A failed build leaves the output of the last
+/// successful build untouched, and output is only rewritten when its content changes.  On
+/// `SIGHUP`, the config file is reloaded and the blocklist rebuilt immediately.  Command line HTTP
+/// settings keep overriding the reloaded config.  Metrics are served on the `[metrics]` listen
+/// address, if configured when the daemon starts.
+///
+/// # Errors
+///
+/// Returns an error if the `SIGHUP` handler cannot be registered, or the metrics address cannot
+/// be bound.  Failed builds, and output files which cannot be written, are logged and retried on
+/// the next interval, rather than returned.
+pub async fn run(
+    config_path: &Path,
+    config: Config,
+    http_overrides: &HttpOverrides,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut config = config;
+    let mut last_good_count: Option<usize> = None;
+    let mut metrics = BuildMetrics::default();
+    let rendered_metrics = Arc::new(Mutex::new(metrics.render()));
+    let mut reload = reload_signal()?;
+    spawn_metrics_endpoint(config.metrics.as_ref(), Some(Arc::clone(&rendered_metrics))).await?;
+
+    loop {
+        let result = build_blocklist_with_metrics(&config, &mut metrics).await;
+        metrics.write_configured_textfile(&config);
+        *rendered_metrics
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner) = metrics.render();
+        match result {
+            Ok(result) => {
+                info!(
+                    "Built blocklist with {} results",
+                    result.hosts.len().to_formatted_string(&Locale::en)
+                );
+                // write failures are already logged, and retried on the next build
+                if let Ok(summaries) = write_blocklist_files(&config.output, &result) {
+                    // hook failures are already logged, and the daemon keeps running
+                    let _ = run_post_write_hooks(&config.hooks.post_write, &summaries).await;
+                }
+                if let Some(value) = &config.zone_transfer {
+                    if let Err(error) = publish_zone(value, &result) {
+                        error!("Unable to publish zone: {error}");
+                    }
+                }
+                last_good_count = Some(result.hosts.len());
+            }
+            Err(error) => {
+                if let Some(value) = last_good_count {
+                    error!(
+                        error_kind = error.kind();
+                        "Error building blocklist, keeping last good blocklist with {} results: {error}",
+                        value.to_formatted_string(&Locale::en)
+                    );
+                } else {
+                    error!(error_kind = error.kind(); "Error building blocklist: {error}");
+                }
+            }
+        }
+
+        let delay = next_delay(&config.daemon);
+        info!("Next build in {}", humantime::format_duration(delay));
+        tokio::select! {
+            () = tokio::time::sleep(delay) => {}
+            () = reload_requested(&mut reload) => {
+                info!("Reloading config from `{}`", config_path.display());
+                match get_config_from_file(config_path) {
+                    Ok(value) => {
+                        config = value;
+                        http_overrides.apply(&mut config.http);
+                    }
+                    Err(error) => error!("{error}, keeping previous config"),
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::next_delay;
+    use crate::file_system::Daemon;
+    use std::time::Duration;
+
+    #[test]
+    fn next_delay_adds_bounded_jitter_to_interval() {
+        // arrange
+        let daemon = Daemon {
+            interval: Duration::from_secs(60),
+            jitter: Duration::from_secs(10),
+        };
+
+        // act
+        let result = next_delay(&daemon);
+
+        // assert
+        assert!(result >= Duration::from_secs(60));
+        assert!(result <= Duration::from_secs(70));
+    }
+
+    #[test]
+    fn next_delay_returns_interval_without_jitter() {
+        // arrange
+        let daemon = Daemon {
+            interval: Duration::from_secs(60),
+            jitter: Duration::ZERO,
+        };
+
+        // act
+        let result = next_delay(&daemon);
+
+        // assert
+        assert_eq!(result, Duration::from_secs(60));
+    }
+}
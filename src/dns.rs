@@ -0,0 +1,853 @@
+use nom::{
+    bytes::complete::take,
+    number::complete::{be_u16, be_u8},
+    sequence::tuple,
+    IResult,
+};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+pub const HEADER_LENGTH: usize = 12;
+
+pub const TYPE_A: u16 = 1;
+pub const TYPE_NS: u16 = 2;
+pub const TYPE_CNAME: u16 = 5;
+pub const TYPE_SOA: u16 = 6;
+pub const TYPE_AAAA: u16 = 28;
+pub const TYPE_IXFR: u16 = 251;
+pub const TYPE_AXFR: u16 = 252;
+pub const CLASS_IN: u16 = 1;
+
+pub const OPCODE_QUERY: u8 = 0;
+pub const OPCODE_NOTIFY: u8 = 4;
+
+pub const RCODE_NOERROR: u8 = 0;
+pub const RCODE_SERVFAIL: u8 = 2;
+pub const RCODE_NXDOMAIN: u8 = 3;
+pub const RCODE_REFUSED: u8 = 5;
+
+const FLAG_QR: u16 = 0x8000;
+const FLAG_AA: u16 = 0x0400;
+const FLAG_RD: u16 = 0x0100;
+const FLAG_RA: u16 = 0x0080;
+const OPCODE_MASK: u16 = 0x7800;
+
+/// Maximum length of a DNS name in presentation format (RFC 1035 section 2.3.4).
+const MAX_NAME_LENGTH: usize = 253;
+
+#[derive(Debug, PartialEq)]
+pub struct Header {
+    pub id: u16,
+    pub flags: u16,
+    pub question_count: u16,
+    pub answer_count: u16,
+    pub authority_count: u16,
+    pub additional_count: u16,
+}
+
+impl Header {
+    pub fn is_response(&self) -> bool {
+        self.flags & FLAG_QR != 0
+    }
+
+    pub fn opcode(&self) -> u8 {
+        // masked and shifted value always fits in four bits
+        u8::try_from((self.flags & OPCODE_MASK) >> 11).unwrap_or_default()
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Question {
+    /// Lower case name, without the trailing dot, for lookups
+    pub name: String,
+
+    /// Name exactly as received, in wire format, echoed back in responses so resolvers using
+    /// 0x20 case randomisation see the case they sent.  Empty for questions built locally
+    pub wire_name: Vec<u8>,
+    pub record_type: u16,
+    pub class: u16,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Query {
+    pub header: Header,
+    pub question: Question,
+}
+
+fn parse_header(input: &[u8]) -> IResult<&[u8], Header> {
+    let (rest, (id, flags, question_count, answer_count, authority_count, additional_count)) =
+        tuple((be_u16, be_u16, be_u16, be_u16, be_u16, be_u16))(input)?;
+    Ok((
+        rest,
+        Header {
+            id,
+            flags,
+            question_count,
+            answer_count,
+            authority_count,
+            additional_count,
+        },
+    ))
+}
+
+/// Parses an uncompressed name, as sent in the question section of a query.  Names are returned
+/// lower case, without the trailing dot.
+fn parse_name(input: &[u8]) -> IResult<&[u8], String> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut rest = input;
+    loop {
+        let (after_length, length) = be_u8(rest)?;
+        if length == 0 {
+            rest = after_length;
+            break;
+        }
+        if length > 63 {
+            return Err(nom::Err::Error(nom::error::Error::new(
+                rest,
+                nom::error::ErrorKind::Verify,
+            )));
+        }
+        let (after_label, label) = take(length)(after_length)?;
+        labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+        rest = after_label;
+    }
+    let name = labels.join(".");
+    if name.len() > MAX_NAME_LENGTH {
+        return Err(nom::Err::Error(nom::error::Error::new(
+            input,
+            nom::error::ErrorKind::Verify,
+        )));
+    }
+    Ok((rest, name))
+}
+
+fn parse_question(input: &[u8]) -> IResult<&[u8], Question> {
+    let (after_name, name) = parse_name(input)?;
+    let wire_name = input[..input.len() - after_name.len()].to_vec();
+    let (rest, (record_type, class)) = tuple((be_u16, be_u16))(after_name)?;
+    Ok((
+        rest,
+        Question {
+            name,
+            wire_name,
+            record_type,
+            class,
+        },
+    ))
+}
+
+/// Parses a query message carrying a single question.  Returns `None` for responses, or messages
+/// which cannot be parsed.
+pub fn parse_query(message: &[u8]) -> Option<Query> {
+    let Ok((rest, header)) = parse_header(message) else {
+        return None;
+    };
+    if header.is_response() || header.question_count != 1 {
+        return None;
+    }
+    let Ok((_rest, question)) = parse_question(rest) else {
+        return None;
+    };
+    Some(Query { header, question })
+}
+
+/// Encodes a name in wire format, without compression.
+pub fn encode_name(name: &str, buffer: &mut Vec<u8>) {
+    for label in name
+        .trim_end_matches('.')
+        .split('.')
+        .filter(|val| !val.is_empty())
+    {
+        let length = u8::try_from(label.len()).expect("DNS labels should be at most 63 octets");
+        buffer.push(length);
+        buffer.extend_from_slice(label.as_bytes());
+    }
+    buffer.push(0);
+}
+
+fn encode_question(question: &Question, buffer: &mut Vec<u8>) {
+    if question.wire_name.is_empty() {
+        encode_name(&question.name, buffer);
+    } else {
+        buffer.extend_from_slice(&question.wire_name);
+    }
+    buffer.extend_from_slice(&question.record_type.to_be_bytes());
+    buffer.extend_from_slice(&question.class.to_be_bytes());
+}
+
+fn encode_response_header(
+    query_header: &Header,
+    response_code: u8,
+    question_count: u16,
+    answer_count: u16,
+    buffer: &mut Vec<u8>,
+) {
+    let flags = FLAG_QR
+        | FLAG_AA
+        | FLAG_RA
+        | (query_header.flags & (OPCODE_MASK | FLAG_RD))
+        | u16::from(response_code & 0x0f);
+    buffer.extend_from_slice(&query_header.id.to_be_bytes());
+    buffer.extend_from_slice(&flags.to_be_bytes());
+    buffer.extend_from_slice(&question_count.to_be_bytes());
+    buffer.extend_from_slice(&answer_count.to_be_bytes());
+    buffer.extend_from_slice(&0u16.to_be_bytes());
+    buffer.extend_from_slice(&0u16.to_be_bytes());
+}
+
+fn encode_header(
+    query_header: &Header,
+    response_code: u8,
+    answer_count: u16,
+    buffer: &mut Vec<u8>,
+) {
+    encode_response_header(query_header, response_code, 1, answer_count, buffer);
+}
+
+/// Appends a resource record whose owner is the question name, referenced with a compression
+/// pointer to offset 12 (the first question).
+fn encode_answer(record_type: u16, ttl: u32, data: &[u8], buffer: &mut Vec<u8>) {
+    buffer.extend_from_slice(&[0xc0, 0x0c]);
+    buffer.extend_from_slice(&record_type.to_be_bytes());
+    buffer.extend_from_slice(&CLASS_IN.to_be_bytes());
+    buffer.extend_from_slice(&ttl.to_be_bytes());
+    let data_length = u16::try_from(data.len()).expect("Record data should fit in a DNS message");
+    buffer.extend_from_slice(&data_length.to_be_bytes());
+    buffer.extend_from_slice(data);
+}
+
+/// Builds a response with no answers, echoing the question.
+pub fn empty_response(query: &Query, response_code: u8) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(HEADER_LENGTH + query.question.name.len() + 6);
+    encode_header(&query.header, response_code, 0, &mut buffer);
+    encode_question(&query.question, &mut buffer);
+    buffer
+}
+
+/// Builds the response for a blocked name.  `A` and `AAAA` queries are answered with the sinkhole
+/// address, when one is configured for that address family, other queries get an empty `NOERROR`
+/// response.  Without any sinkhole, the response is `NXDOMAIN`.
+pub fn blocked_response(
+    query: &Query,
+    sinkhole_ipv4: Option<Ipv4Addr>,
+    sinkhole_ipv6: Option<Ipv6Addr>,
+    ttl: u32,
+) -> Vec<u8> {
+    if sinkhole_ipv4.is_none() && sinkhole_ipv6.is_none() {
+        return empty_response(query, RCODE_NXDOMAIN);
+    }
+    let data: Option<Vec<u8>> = match (query.question.record_type, sinkhole_ipv4, sinkhole_ipv6) {
+        (TYPE_A, Some(address), _) => Some(address.octets().to_vec()),
+        (TYPE_AAAA, _, Some(address)) => Some(address.octets().to_vec()),
+        _ => None,
+    };
+    let Some(data) = data else {
+        return empty_response(query, RCODE_NOERROR);
+    };
+
+    let mut buffer = Vec::with_capacity(HEADER_LENGTH + query.question.name.len() + 34);
+    encode_header(&query.header, RCODE_NOERROR, 1, &mut buffer);
+    encode_question(&query.question, &mut buffer);
+    encode_answer(query.question.record_type, ttl, &data, &mut buffer);
+    buffer
+}
+
+/// Largest message built for zone transfers over TCP, leaving headroom below the 65,535 octet
+/// limit for the final record added.
+const MAX_TRANSFER_MESSAGE_LENGTH: usize = 16_384;
+
+/// Reads a name starting at `offset` in `message`, following compression pointers.  Returns the
+/// lower case name, without the trailing dot, and the offset just past the name as it appears at
+/// `offset`.
+pub fn read_name(message: &[u8], offset: usize) -> Option<(String, usize)> {
+    let mut labels: Vec<String> = Vec::new();
+    let mut position = offset;
+    let mut end: Option<usize> = None;
+    loop {
+        let length = *message.get(position)?;
+        match length {
+            0 => {
+                let name = labels.join(".");
+                if name.len() > MAX_NAME_LENGTH {
+                    return None;
+                }
+                return Some((name, end.unwrap_or(position + 1)));
+            }
+            1..=63 => {
+                let label = message.get(position + 1..position + 1 + usize::from(length))?;
+                labels.push(String::from_utf8_lossy(label).to_ascii_lowercase());
+                position += 1 + usize::from(length);
+            }
+            0xc0..=0xff => {
+                let pointer = usize::from(u16::from_be_bytes([
+                    length & 0x3f,
+                    *message.get(position + 1)?,
+                ]));
+                // only accept pointers to earlier octets, which rules out loops
+                if pointer >= position {
+                    return None;
+                }
+                end.get_or_insert(position + 2);
+                position = pointer;
+            }
+            _ => return None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ResourceRecord {
+    pub name: String,
+    pub record_type: u16,
+    pub class: u16,
+    pub ttl: u32,
+
+    /// Offset of the record data within the message, needed to read compressed names in the data
+    pub data_offset: usize,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Message {
+    pub header: Header,
+    pub questions: Vec<Question>,
+    pub answers: Vec<ResourceRecord>,
+    pub authorities: Vec<ResourceRecord>,
+    pub additionals: Vec<ResourceRecord>,
+}
+
+fn read_u16(message: &[u8], offset: usize) -> Option<u16> {
+    Some(u16::from_be_bytes(
+        message.get(offset..offset + 2)?.try_into().ok()?,
+    ))
+}
+
+fn read_u32(message: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_be_bytes(
+        message.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+fn read_resource_records(
+    message: &[u8],
+    offset: usize,
+    count: u16,
+) -> Option<(Vec<ResourceRecord>, usize)> {
+    let mut result = Vec::with_capacity(usize::from(count));
+    let mut position = offset;
+    for _ in 0..count {
+        let (name, after_name) = read_name(message, position)?;
+        let record_type = read_u16(message, after_name)?;
+        let class = read_u16(message, after_name + 2)?;
+        let ttl = read_u32(message, after_name + 4)?;
+        let data_length = usize::from(read_u16(message, after_name + 8)?);
+        let data_offset = after_name + 10;
+        let data = message
+            .get(data_offset..data_offset + data_length)?
+            .to_vec();
+        result.push(ResourceRecord {
+            name,
+            record_type,
+            class,
+            ttl,
+            data_offset,
+            data,
+        });
+        position = data_offset + data_length;
+    }
+    Some((result, position))
+}
+
+/// Parses a complete message, including compressed names.  Returns `None` for truncated or
+/// malformed messages.
+pub fn parse_message(message: &[u8]) -> Option<Message> {
+    let (_, header) = parse_header(message).ok()?;
+    let mut position = HEADER_LENGTH;
+    let mut questions = Vec::with_capacity(usize::from(header.question_count));
+    for _ in 0..header.question_count {
+        let (name, after_name) = read_name(message, position)?;
+        questions.push(Question {
+            name,
+            wire_name: Vec::new(),
+            record_type: read_u16(message, after_name)?,
+            class: read_u16(message, after_name + 2)?,
+        });
+        position = after_name + 4;
+    }
+    let (answers, position) = read_resource_records(message, position, header.answer_count)?;
+    let (authorities, position) = read_resource_records(message, position, header.authority_count)?;
+    let (additionals, _) = read_resource_records(message, position, header.additional_count)?;
+    Some(Message {
+        header,
+        questions,
+        answers,
+        authorities,
+        additionals,
+    })
+}
+
+/// Serial from an `SOA` record's data, skipping the `MNAME` and `RNAME` names.
+pub fn soa_serial(message: &[u8], record: &ResourceRecord) -> Option<u32> {
+    if record.record_type != TYPE_SOA {
+        return None;
+    }
+    let (_, after_primary) = read_name(message, record.data_offset)?;
+    let (_, after_mailbox) = read_name(message, after_primary)?;
+    read_u32(message, after_mailbox)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Soa {
+    pub mname: String,
+    pub rname: String,
+    pub serial: u32,
+    pub refresh: u32,
+    pub retry: u32,
+    pub expire: u32,
+    pub minimum: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RecordData {
+    Soa(Soa),
+    Ns(String),
+    Cname(String),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    pub name: String,
+    pub ttl: u32,
+    pub data: RecordData,
+}
+
+impl Record {
+    fn record_type(&self) -> u16 {
+        match self.data {
+            RecordData::Soa(_) => TYPE_SOA,
+            RecordData::Ns(_) => TYPE_NS,
+            RecordData::Cname(_) => TYPE_CNAME,
+        }
+    }
+
+    /// Appends the record in wire format, without compression.
+    pub fn encode(&self, buffer: &mut Vec<u8>) {
+        encode_name(&self.name, buffer);
+        buffer.extend_from_slice(&self.record_type().to_be_bytes());
+        buffer.extend_from_slice(&CLASS_IN.to_be_bytes());
+        buffer.extend_from_slice(&self.ttl.to_be_bytes());
+        let mut data = Vec::new();
+        match &self.data {
+            RecordData::Soa(Soa {
+                mname,
+                rname,
+                serial,
+                refresh,
+                retry,
+                expire,
+                minimum,
+            }) => {
+                encode_name(mname, &mut data);
+                encode_name(rname, &mut data);
+                for value in [serial, refresh, retry, expire, minimum] {
+                    data.extend_from_slice(&value.to_be_bytes());
+                }
+            }
+            RecordData::Ns(value) | RecordData::Cname(value) => encode_name(value, &mut data),
+        }
+        let data_length =
+            u16::try_from(data.len()).expect("Record data should fit in a DNS message");
+        buffer.extend_from_slice(&data_length.to_be_bytes());
+        buffer.extend_from_slice(&data);
+    }
+}
+
+/// Builds a single response message answering the query with `records`.
+pub fn answer_response(query: &Query, records: &[Record]) -> Vec<u8> {
+    let answer_count = u16::try_from(records.len()).unwrap_or(u16::MAX);
+    let mut buffer = Vec::with_capacity(MAX_TRANSFER_MESSAGE_LENGTH);
+    encode_header(&query.header, RCODE_NOERROR, answer_count, &mut buffer);
+    encode_question(&query.question, &mut buffer);
+    for record in records.iter().take(usize::from(answer_count)) {
+        record.encode(&mut buffer);
+    }
+    buffer
+}
+
+/// Splits a zone transfer into as many response messages as needed, each carrying the question,
+/// for sending over TCP (RFC 5936 section 2.2).
+pub fn transfer_responses(query: &Query, records: &[Record]) -> Vec<Vec<u8>> {
+    let mut result = Vec::new();
+    let mut encoded_records: Vec<u8> = Vec::with_capacity(MAX_TRANSFER_MESSAGE_LENGTH);
+    let mut record_count: u16 = 0;
+    let finish = |encoded_records: &mut Vec<u8>, record_count: u16, result: &mut Vec<Vec<u8>>| {
+        let mut buffer = Vec::with_capacity(encoded_records.len() + 512);
+        encode_header(&query.header, RCODE_NOERROR, record_count, &mut buffer);
+        encode_question(&query.question, &mut buffer);
+        buffer.append(encoded_records);
+        result.push(buffer);
+    };
+    for record in records {
+        record.encode(&mut encoded_records);
+        record_count += 1;
+        if encoded_records.len() >= MAX_TRANSFER_MESSAGE_LENGTH {
+            finish(&mut encoded_records, record_count, &mut result);
+            record_count = 0;
+        }
+    }
+    if record_count > 0 || result.is_empty() {
+        finish(&mut encoded_records, record_count, &mut result);
+    }
+    result
+}
+
+/// Builds a `NOTIFY` message for the zone, carrying its current `SOA` (RFC 1996 section 3.7).
+pub fn notify_message(id: u16, soa: &Record) -> Vec<u8> {
+    let flags = (u16::from(OPCODE_NOTIFY) << 11) | FLAG_AA;
+    let mut buffer = Vec::with_capacity(512);
+    buffer.extend_from_slice(&id.to_be_bytes());
+    buffer.extend_from_slice(&flags.to_be_bytes());
+    for count in [1u16, 1, 0, 0] {
+        buffer.extend_from_slice(&count.to_be_bytes());
+    }
+    encode_question(
+        &Question {
+            name: soa.name.clone(),
+            wire_name: Vec::new(),
+            record_type: TYPE_SOA,
+            class: CLASS_IN,
+        },
+        &mut buffer,
+    );
+    soa.encode(&mut buffer);
+    buffer
+}
+
+/// Builds a recursive query for `name`.
+pub fn query_message(id: u16, name: &str, record_type: u16) -> Vec<u8> {
+    let mut buffer = Vec::with_capacity(HEADER_LENGTH + name.len() + 6);
+    buffer.extend_from_slice(&id.to_be_bytes());
+    buffer.extend_from_slice(&FLAG_RD.to_be_bytes());
+    for count in [1u16, 0, 0, 0] {
+        buffer.extend_from_slice(&count.to_be_bytes());
+    }
+    encode_question(
+        &Question {
+            name: name.to_string(),
+            wire_name: Vec::new(),
+            record_type,
+            class: CLASS_IN,
+        },
+        &mut buffer,
+    );
+    buffer
+}
+
+/// Addresses in the `A` and `AAAA` records of a response's answer section.
+pub fn answer_addresses(message: &Message) -> Vec<IpAddr> {
+    message
+        .answers
+        .iter()
+        .filter_map(|record| match (record.record_type, record.data.len()) {
+            (TYPE_A, 4) => {
+                let octets: [u8; 4] = record.data[..].try_into().ok()?;
+                Some(IpAddr::from(octets))
+            }
+            (TYPE_AAAA, 16) => {
+                let octets: [u8; 16] = record.data[..].try_into().ok()?;
+                Some(IpAddr::from(octets))
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        answer_addresses, answer_response, blocked_response, empty_response, encode_name,
+        notify_message, parse_message, parse_name, parse_query, query_message as recursive_query,
+        read_name, soa_serial, transfer_responses, Header, Query, Question, Record, RecordData,
+        Soa, OPCODE_NOTIFY, RCODE_NXDOMAIN, RCODE_SERVFAIL, TYPE_A, TYPE_AAAA, TYPE_AXFR,
+        TYPE_CNAME, TYPE_SOA,
+    };
+    use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+    fn query_message(id: u16, name: &str, record_type: u16) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(&id.to_be_bytes());
+        result.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        encode_name(name, &mut result);
+        result.extend_from_slice(&record_type.to_be_bytes());
+        result.extend_from_slice(&[0x00, 0x01]);
+        result
+    }
+
+    #[test]
+    fn parse_name_parses_valid_name() {
+        // arrange
+        let input = b"\x03www\x07Example\x03com\x00\x00\x01";
+
+        // act
+        let result = parse_name(input);
+
+        // assert
+        assert_eq!(
+            result,
+            Ok((&b"\x00\x01"[..], String::from("www.example.com")))
+        );
+    }
+
+    #[test]
+    fn parse_name_fails_to_parse_compressed_or_truncated_name() {
+        // arrange
+        let input_0 = b"\xc0\x0c";
+        let input_1 = b"\x03www\x07exam";
+
+        // act
+        let result_0 = parse_name(input_0);
+        let result_1 = parse_name(input_1);
+
+        // assert
+        assert!(result_0.is_err());
+        assert!(result_1.is_err());
+    }
+
+    #[test]
+    fn parse_query_parses_valid_query() {
+        // arrange
+        let message = query_message(0x1234, "ads.example.com", TYPE_AAAA);
+
+        // act
+        let result = parse_query(&message);
+
+        // assert
+        assert_eq!(
+            result,
+            Some(Query {
+                header: Header {
+                    id: 0x1234,
+                    flags: 0x0100,
+                    question_count: 1,
+                    answer_count: 0,
+                    authority_count: 0,
+                    additional_count: 0
+                },
+                question: Question {
+                    name: String::from("ads.example.com"),
+                    wire_name: b"\x03ads\x07example\x03com\x00".to_vec(),
+                    record_type: TYPE_AAAA,
+                    class: 1
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn parse_query_ignores_responses_and_truncated_messages() {
+        // arrange
+        let mut response = query_message(1, "example.com", TYPE_A);
+        response[2] |= 0x80;
+        let truncated = &query_message(1, "example.com", TYPE_A)[..10];
+
+        // act
+        let result_0 = parse_query(&response);
+        let result_1 = parse_query(truncated);
+
+        // assert
+        assert_eq!(result_0, None);
+        assert_eq!(result_1, None);
+    }
+
+    #[test]
+    fn empty_response_echoes_question_with_response_code() {
+        // arrange
+        let message = query_message(0xbeef, "example.com", TYPE_A);
+        let query = parse_query(&message).unwrap();
+
+        // act
+        let result = empty_response(&query, RCODE_SERVFAIL);
+
+        // assert
+        assert_eq!(&result[0..2], &[0xbe, 0xef]);
+        assert_eq!(&result[2..4], &[0x85, 0x82]);
+        assert_eq!(&result[4..12], &[0, 1, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(&result[12..], &message[12..]);
+    }
+
+    #[test]
+    fn empty_response_keeps_question_name_case() {
+        // arrange
+        let message = query_message(0xbeef, "AdS.eXample.COM", TYPE_A);
+        let query = parse_query(&message).unwrap();
+
+        // act
+        let result = empty_response(&query, RCODE_NXDOMAIN);
+
+        // assert
+        assert_eq!(query.question.name, "ads.example.com");
+        assert_eq!(&result[12..], &message[12..]);
+    }
+
+    #[test]
+    fn blocked_response_returns_nxdomain_without_sinkhole() {
+        // arrange
+        let message = query_message(7, "ads.example.com", TYPE_A);
+        let query = parse_query(&message).unwrap();
+
+        // act
+        let result = blocked_response(&query, None, None, 60);
+
+        // assert
+        assert_eq!(result[3] & 0x0f, RCODE_NXDOMAIN);
+        assert_eq!(&result[6..8], &[0, 0]);
+    }
+
+    #[test]
+    fn blocked_response_answers_with_sinkhole_address() {
+        // arrange
+        let message_0 = query_message(7, "ads.example.com", TYPE_A);
+        let query_0 = parse_query(&message_0).unwrap();
+        let message_1 = query_message(8, "ads.example.com", TYPE_AAAA);
+        let query_1 = parse_query(&message_1).unwrap();
+
+        // act
+        let result_0 = blocked_response(&query_0, Some(Ipv4Addr::UNSPECIFIED), None, 60);
+        let result_1 = blocked_response(&query_1, Some(Ipv4Addr::UNSPECIFIED), None, 60);
+
+        // assert
+        assert_eq!(result_0[3] & 0x0f, 0);
+        assert_eq!(&result_0[6..8], &[0, 1]);
+        assert_eq!(&result_0[result_0.len() - 6..], &[0, 4, 0, 0, 0, 0]);
+        assert_eq!(result_1[3] & 0x0f, 0);
+        assert_eq!(&result_1[6..8], &[0, 0]);
+    }
+
+    fn soa_record(serial: u32) -> Record {
+        Record {
+            name: String::from("rpz.example"),
+            ttl: 60,
+            data: RecordData::Soa(Soa {
+                mname: String::from("localhost"),
+                rname: String::from("root.localhost"),
+                serial,
+                refresh: 10_800,
+                retry: 3_600,
+                expire: 604_800,
+                minimum: 3_600,
+            }),
+        }
+    }
+
+    #[test]
+    fn read_name_follows_compression_pointers() {
+        // arrange
+        let mut message = vec![0u8; 12];
+        encode_name("example.com", &mut message);
+        message.extend_from_slice(&[3, b'w', b'w', b'w', 0xc0, 12, 0xc0, 12]);
+
+        // act
+        let result_0 = read_name(&message, 12);
+        let result_1 = read_name(&message, 25);
+        let result_2 = read_name(&message, 31);
+        let result_3 = read_name(&[0xc0, 0], 0);
+
+        // assert
+        assert_eq!(result_0, Some((String::from("example.com"), 25)));
+        assert_eq!(result_1, Some((String::from("www.example.com"), 31)));
+        assert_eq!(result_2, Some((String::from("example.com"), 33)));
+        assert_eq!(result_3, None);
+    }
+
+    #[test]
+    fn parse_message_reads_encoded_records() {
+        // arrange
+        let message = query_message(9, "rpz.example", TYPE_SOA);
+        let query = parse_query(&message).unwrap();
+        let records = [
+            soa_record(2024),
+            Record {
+                name: String::from("ads.example.com.rpz.example"),
+                ttl: 60,
+                data: RecordData::Cname(String::new()),
+            },
+        ];
+
+        // act
+        let response = answer_response(&query, &records);
+        let result = parse_message(&response).unwrap();
+
+        // assert
+        assert_eq!(result.header.id, 9);
+        assert_eq!(result.questions.len(), 1);
+        assert_eq!(result.questions[0].name, query.question.name);
+        assert_eq!(result.questions[0].record_type, TYPE_SOA);
+        assert_eq!(result.answers.len(), 2);
+        assert_eq!(soa_serial(&response, &result.answers[0]), Some(2024));
+        assert_eq!(result.answers[1].name, "ads.example.com.rpz.example");
+        assert_eq!(result.answers[1].record_type, TYPE_CNAME);
+        assert_eq!(result.answers[1].data, vec![0]);
+    }
+
+    #[test]
+    fn transfer_responses_splits_large_transfers() {
+        // arrange
+        let message = query_message(10, "rpz.example", TYPE_AXFR);
+        let query = parse_query(&message).unwrap();
+        let mut records = vec![soa_record(1)];
+        for index in 0..2_000 {
+            records.push(Record {
+                name: format!("host-{index}.example.com.rpz.example"),
+                ttl: 60,
+                data: RecordData::Cname(String::new()),
+            });
+        }
+        records.push(soa_record(1));
+
+        // act
+        let result = transfer_responses(&query, &records);
+
+        // assert
+        assert!(result.len() > 1);
+        let answer_count: usize = result
+            .iter()
+            .map(|val| parse_message(val).unwrap().answers.len())
+            .sum();
+        assert_eq!(answer_count, 2_002);
+    }
+
+    #[test]
+    fn notify_message_carries_zone_soa() {
+        // arrange
+        let soa = soa_record(7);
+
+        // act
+        let result = notify_message(11, &soa);
+
+        // assert
+        let message = parse_message(&result).unwrap();
+        assert_eq!(message.header.opcode(), OPCODE_NOTIFY);
+        assert!(!message.header.is_response());
+        assert_eq!(message.questions[0].name, "rpz.example");
+        assert_eq!(message.questions[0].record_type, TYPE_SOA);
+        assert_eq!(soa_serial(&result, &message.answers[0]), Some(7));
+    }
+
+    #[test]
+    fn answer_addresses_reads_addresses_from_answers() {
+        // arrange
+        let query_v4 = parse_query(&recursive_query(21, "ads.example.com", TYPE_A)).unwrap();
+        let query_v6 = parse_query(&recursive_query(22, "ads.example.com", TYPE_AAAA)).unwrap();
+        let response_v4 = blocked_response(&query_v4, Some(Ipv4Addr::new(192, 0, 2, 1)), None, 60);
+        let response_v6 = blocked_response(&query_v6, None, Some(Ipv6Addr::LOCALHOST), 60);
+
+        // act
+        let result_v4 = answer_addresses(&parse_message(&response_v4).unwrap());
+        let result_v6 = answer_addresses(&parse_message(&response_v6).unwrap());
+
+        // assert
+        assert_eq!(query_v4.header.id, 21);
+        assert_eq!(query_v4.question.name, "ads.example.com");
+        assert_eq!(result_v4, vec![IpAddr::from(Ipv4Addr::new(192, 0, 2, 1))]);
+        assert_eq!(result_v6, vec![IpAddr::from(Ipv6Addr::LOCALHOST)]);
+    }
+}
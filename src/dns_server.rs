@@ -0,0 +1,345 @@
+use crate::dns::{blocked_response, empty_response, parse_query, Query, CLASS_IN, RCODE_SERVFAIL};
+use ahash::RandomState;
+use log::{debug, error, info, trace};
+use std::{
+    collections::HashSet,
+    io,
+    net::{Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream, UdpSocket},
+    time::timeout,
+};
+use url::Host;
+
+/// Largest message accepted over UDP, allowing for EDNS(0) payloads.
+const MAX_UDP_MESSAGE_LENGTH: usize = 4096;
+
+const UPSTREAM_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time a TCP client may leave a connection idle, or take sending a query, before it is closed
+/// (RFC 7766 section 6.2.3).
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct ServerConfig {
+    pub upstream: SocketAddr,
+    pub sinkhole_ipv4: Option<Ipv4Addr>,
+    pub sinkhole_ipv6: Option<Ipv6Addr>,
+    pub ttl: u32,
+}
+
+pub struct Server {
+    config: ServerConfig,
+    blocked_domains: HashSet<String, RandomState>,
+}
+
+impl Server {
+    #[must_use]
+    pub fn new(config: ServerConfig, blocklist_domains: &[Host]) -> Self {
+        let blocked_domains = blocklist_domains
+            .iter()
+            .filter_map(|val| match val {
+                Host::Domain(domain) => Some(domain.clone()),
+                Host::Ipv4(_) | Host::Ipv6(_) => None,
+            })
+            .collect();
+        Server {
+            config,
+            blocked_domains,
+        }
+    }
+
+    /// A name is blocked when it, or any parent domain, is in the blocklist, matching the
+    /// `example.com` and `*.example.com` pair of records written to the RPZ file.
+    #[must_use]
+    pub fn is_blocked(&self, name: &str) -> bool {
+        let mut candidate = name.trim_end_matches('.');
+        loop {
+            if self.blocked_domains.contains(candidate) {
+                return true;
+            }
+            match candidate.split_once('.') {
+                Some((_, parent)) => candidate = parent,
+                None => return false,
+            }
+        }
+    }
+
+    /// Returns the local response for a blocked query, or `None` if the query should be forwarded
+    /// upstream.
+    fn local_response(&self, query: &Query) -> Option<Vec<u8>> {
+        if query.header.opcode() != 0 || query.question.class != CLASS_IN {
+            return None;
+        }
+        if self.is_blocked(&query.question.name) {
+            debug!("Blocked query for `{}`", query.question.name);
+            let ServerConfig {
+                sinkhole_ipv4,
+                sinkhole_ipv6,
+                ttl,
+                ..
+            } = self.config;
+            return Some(blocked_response(query, sinkhole_ipv4, sinkhole_ipv6, ttl));
+        }
+        None
+    }
+
+    async fn forward_udp(&self, message: &[u8]) -> io::Result<Vec<u8>> {
+        let bind_address: SocketAddr = if self.config.upstream.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(bind_address).await?;
+        socket.connect(self.config.upstream).await?;
+        socket.send(message).await?;
+        let mut buffer = vec![0u8; MAX_UDP_MESSAGE_LENGTH];
+        let length = timeout(UPSTREAM_TIMEOUT, socket.recv(&mut buffer)).await??;
+        buffer.truncate(length);
+        Ok(buffer)
+    }
+
+    async fn forward_tcp(&self, message: &[u8]) -> io::Result<Vec<u8>> {
+        let mut stream =
+            timeout(UPSTREAM_TIMEOUT, TcpStream::connect(self.config.upstream)).await??;
+        write_tcp_message(&mut stream, message).await?;
+        let response = timeout(UPSTREAM_TIMEOUT, read_tcp_message(&mut stream)).await??;
+        Ok(response)
+    }
+
+    async fn handle_message(&self, message: &[u8], tcp: bool) -> Option<Vec<u8>> {
+        let query = parse_query(message);
+        if let Some(value) = query.as_ref().and_then(|val| self.local_response(val)) {
+            return Some(value);
+        }
+        let forwarded = if tcp {
+            self.forward_tcp(message).await
+        } else {
+            self.forward_udp(message).await
+        };
+        match forwarded {
+            Ok(value) => Some(value),
+            Err(error) => {
+                error!("Error forwarding query to upstream resolver: {error}");
+                query.map(|val| empty_response(&val, RCODE_SERVFAIL))
+            }
+        }
+    }
+
+    async fn handle_tcp_connection(self: Arc<Self>, mut stream: TcpStream) -> io::Result<()> {
+        loop {
+            let message = match timeout(TCP_IDLE_TIMEOUT, read_tcp_message(&mut stream)).await {
+                Ok(Ok(value)) => value,
+                Ok(Err(error)) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Ok(Err(error)) => return Err(error),
+                Err(_) => {
+                    trace!("Closing idle TCP connection");
+                    return Ok(());
+                }
+            };
+            if let Some(response) = self.handle_message(&message, true).await {
+                write_tcp_message(&mut stream, &response).await?;
+            }
+        }
+    }
+
+    async fn serve_udp(self: Arc<Self>, socket: UdpSocket) -> io::Result<()> {
+        let socket = Arc::new(socket);
+        let mut buffer = vec![0u8; MAX_UDP_MESSAGE_LENGTH];
+        loop {
+            let (length, peer) = socket.recv_from(&mut buffer).await?;
+            let message = buffer[..length].to_vec();
+            let server = Arc::clone(&self);
+            let socket = Arc::clone(&socket);
+            tokio::spawn(async move {
+                if let Some(response) = server.handle_message(&message, false).await {
+                    if let Err(error) = socket.send_to(&response, peer).await {
+                        error!("Error sending response to {peer}: {error}");
+                    }
+                }
+            });
+        }
+    }
+
+    async fn serve_tcp(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = Arc::clone(&self);
+            tokio::spawn(async move {
+                if let Err(error) = server.handle_tcp_connection(stream).await {
+                    trace!("Closed TCP connection from {peer}: {error}");
+                }
+            });
+        }
+    }
+
+    /// Answers queries on the UDP socket and TCP listener until either fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if receiving on the UDP socket or accepting a TCP connection fails.
+    pub async fn run(self, udp_socket: UdpSocket, tcp_listener: TcpListener) -> io::Result<()> {
+        if let Ok(value) = udp_socket.local_addr() {
+            info!("Listening for DNS queries on {value}");
+        }
+        let server = Arc::new(self);
+        tokio::try_join!(
+            Arc::clone(&server).serve_udp(udp_socket),
+            server.serve_tcp(tcp_listener)
+        )?;
+        Ok(())
+    }
+}
+
+pub(crate) async fn read_tcp_message(stream: &mut TcpStream) -> io::Result<Vec<u8>> {
+    let length = stream.read_u16().await?;
+    let mut buffer = vec![0u8; usize::from(length)];
+    stream.read_exact(&mut buffer).await?;
+    Ok(buffer)
+}
+
+async fn write_tcp_message(stream: &mut TcpStream, message: &[u8]) -> io::Result<()> {
+    let length = u16::try_from(message.len())
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "DNS message too long"))?;
+    let mut buffer = Vec::with_capacity(message.len() + 2);
+    buffer.extend_from_slice(&length.to_be_bytes());
+    buffer.extend_from_slice(message);
+    stream.write_all(&buffer).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_tcp_message, write_tcp_message, Server, ServerConfig};
+    use crate::dns::{encode_name, RCODE_NXDOMAIN, TYPE_A};
+    use std::{net::SocketAddr, time::Duration};
+    use tokio::{
+        net::{TcpListener, TcpStream, UdpSocket},
+        time::timeout,
+    };
+    use url::Host;
+
+    const UPSTREAM_MARKER: &[u8] = b"upstream";
+
+    fn query_message(id: u16, name: &str) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(&id.to_be_bytes());
+        result.extend_from_slice(&[0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        encode_name(name, &mut result);
+        result.extend_from_slice(&TYPE_A.to_be_bytes());
+        result.extend_from_slice(&[0x00, 0x01]);
+        result
+    }
+
+    /// Upstream resolver stub, which replies to every UDP and TCP message with a marker.
+    async fn stub_upstream() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap(); // DevSkim: ignore DS162092 - use of local host IP is in test
+        let address = socket.local_addr().unwrap();
+        let listener = TcpListener::bind(address).await.unwrap();
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 512];
+            while let Ok((_, peer)) = socket.recv_from(&mut buffer).await {
+                socket.send_to(UPSTREAM_MARKER, peer).await.unwrap();
+            }
+        });
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let _ = read_tcp_message(&mut stream).await.unwrap();
+                write_tcp_message(&mut stream, UPSTREAM_MARKER)
+                    .await
+                    .unwrap();
+            }
+        });
+        address
+    }
+
+    async fn start_server(upstream: SocketAddr) -> SocketAddr {
+        let config = ServerConfig {
+            upstream,
+            sinkhole_ipv4: None,
+            sinkhole_ipv6: None,
+            ttl: 60,
+        };
+        let server = Server::new(config, &[Host::parse("example.com").unwrap()]);
+        let udp_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap(); // DevSkim: ignore DS162092 - use of local host IP is in test
+        let address = udp_socket.local_addr().unwrap();
+        let tcp_listener = TcpListener::bind(address).await.unwrap();
+        tokio::spawn(server.run(udp_socket, tcp_listener));
+        address
+    }
+
+    async fn udp_exchange(server: SocketAddr, message: &[u8]) -> Vec<u8> {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap(); // DevSkim: ignore DS162092 - use of local host IP is in test
+        socket.send_to(message, server).await.unwrap();
+        let mut buffer = [0u8; 512];
+        let length = timeout(Duration::from_secs(5), socket.recv(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        buffer[..length].to_vec()
+    }
+
+    #[test]
+    fn is_blocked_matches_blocked_domains_and_subdomains() {
+        // arrange
+        let config = ServerConfig {
+            upstream: "127.0.0.1:53".parse().unwrap(), // DevSkim: ignore DS162092 - use of local host IP is in test
+            sinkhole_ipv4: None,
+            sinkhole_ipv6: None,
+            ttl: 60,
+        };
+        let server = Server::new(config, &[Host::parse("ads.example.com").unwrap()]);
+
+        // act
+        let result_0 = server.is_blocked("ads.example.com");
+        let result_1 = server.is_blocked("tracker.ads.example.com.");
+        let result_2 = server.is_blocked("example.com");
+        let result_3 = server.is_blocked("bads.example.com");
+
+        // assert
+        assert!(result_0);
+        assert!(result_1);
+        assert!(!result_2);
+        assert!(!result_3);
+    }
+
+    #[tokio::test]
+    async fn server_answers_blocked_udp_queries_locally_and_forwards_others() {
+        // arrange
+        let upstream = stub_upstream().await;
+        let server = start_server(upstream).await;
+
+        // act
+        let result_0 = udp_exchange(server, &query_message(1, "www.example.com")).await;
+        let result_1 = udp_exchange(server, &query_message(2, "example.org")).await;
+
+        // assert
+        assert_eq!(&result_0[0..2], &[0, 1]);
+        assert_eq!(result_0[3] & 0x0f, RCODE_NXDOMAIN);
+        assert_eq!(result_1, UPSTREAM_MARKER);
+    }
+
+    #[tokio::test]
+    async fn server_answers_blocked_tcp_queries_locally_and_forwards_others() {
+        // arrange
+        let upstream = stub_upstream().await;
+        let server = start_server(upstream).await;
+        let mut stream = TcpStream::connect(server).await.unwrap();
+
+        // act
+        write_tcp_message(&mut stream, &query_message(1, "example.com"))
+            .await
+            .unwrap();
+        let result_0 = read_tcp_message(&mut stream).await.unwrap();
+        write_tcp_message(&mut stream, &query_message(2, "example.org"))
+            .await
+            .unwrap();
+        let result_1 = read_tcp_message(&mut stream).await.unwrap();
+
+        // assert
+        assert_eq!(result_0[3] & 0x0f, RCODE_NXDOMAIN);
+        assert_eq!(result_1, UPSTREAM_MARKER);
+    }
+}
@@ -1,13 +1,32 @@
 use crate::{
-    parse::{domainlist as parse_domainlist, hostfile as parse_hostfile},
+    file_system::{BlocklistSource, Blocklists, Http},
+    parse::{domainlist as parse_domainlist, hostfile as parse_hostfile, iplist as parse_iplist},
     Source, SourceType,
 };
 use ahash::RandomState;
-use futures::{Future, Stream, StreamExt};
+use ipnet::IpNet;
 use log::info;
-use std::{collections::HashSet, error::Error};
-use url::Host;
+use minisign_verify::{PublicKey, Signature};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    error::Error,
+    fs,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, SemaphorePermit};
+use url::{Host, Url};
+
+/// Merged hosts, each with the IDs of the sources listing it.  Source IDs are indexes into the
+/// list of sources fetched.
+pub type Provenance = HashMap<Host, Vec<usize>, RandomState>;
 
+/// Errors fetching a blocklist source.
 #[derive(thiserror::Error, Debug)]
 pub enum AppError {
     #[error("Error fetching blocklist `{url}`: only received part of the file.  The network connection may be unstable.")]
@@ -26,31 +45,414 @@ pub enum AppError {
 
     #[error("Error fetching blocklist `{url}`.  Check the URL is correct an connection is up.")]
     Fetch { url: String },
+
+    #[error("Unable to set up the HTTP client: {message}")]
+    Client { message: String },
+
+    #[error("Error fetching blocklist `{url}`: exceeded the limit of {limit}.  Check the URL is for the intended list, or raise the limit.")]
+    LimitExceeded { url: String, limit: String },
+
+    #[error("Error fetching blocklist `{url}`: received {content} rather than a text list.  A captive portal or server error page might be in the way.")]
+    UnexpectedContent { url: String, content: &'static str },
+
+    #[error("Error verifying blocklist `{url}`: {reason}.  The list or its mirror might have been tampered with.")]
+    Verification { url: String, reason: String },
+}
+
+impl AppError {
+    /// Short name of the error variant, for metrics labels.
+    #[must_use]
+    pub fn kind(&self) -> &'static str {
+        match self {
+            AppError::IncompleteBody { .. } => "incomplete_body",
+            AppError::FetchBody { .. } => "fetch_body",
+            AppError::FetchParse { .. } => "fetch_parse",
+            AppError::FetchRequest { .. } => "fetch_request",
+            AppError::Fetch { .. } => "fetch",
+            AppError::Client { .. } => "client",
+            AppError::LimitExceeded { .. } => "limit_exceeded",
+            AppError::UnexpectedContent { .. } => "unexpected_content",
+            AppError::Verification { .. } => "verification",
+        }
+    }
+}
+
+/// Outcome of fetching one source body.
+#[derive(Clone, Debug)]
+pub struct FetchStats {
+    pub url: String,
+    pub duration: Duration,
+
+    /// Size of the body received, or zero if the fetch failed
+    pub bytes: usize,
+
+    /// `AppError` kind, if the fetch failed
+    pub error_kind: Option<&'static str>,
+}
+
+/// Requests in flight to one origin, and when the next may start.
+struct OriginLimit {
+    permits: Arc<Semaphore>,
+    next_start: tokio::sync::Mutex<Instant>,
+}
+
+/// Limits on requests to each origin (scheme, host and port), so several lists published on the
+/// same server are not all fetched from it at once.
+struct OriginLimits {
+    concurrency: usize,
+
+    /// Time between the start of one request to an origin and the next
+    delay: Duration,
+    origins: Mutex<HashMap<String, Arc<OriginLimit>>>,
+}
+
+impl OriginLimits {
+    fn new(concurrency: usize, delay: Duration) -> Self {
+        OriginLimits {
+            concurrency: concurrency.max(1),
+            delay,
+            origins: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Waits until a request to the origin of `url` may start.  The request counts against the
+    /// origin's concurrency limit until the returned permit is dropped.
+    async fn acquire(&self, url: &str) -> OwnedSemaphorePermit {
+        let origin = Url::parse(url).map_or_else(
+            |_| url.to_string(),
+            |val| val.origin().ascii_serialization(),
+        );
+        let limit = Arc::clone(
+            self.origins
+                .lock()
+                .unwrap()
+                .entry(origin)
+                .or_insert_with(|| {
+                    Arc::new(OriginLimit {
+                        permits: Arc::new(Semaphore::new(self.concurrency)),
+                        next_start: tokio::sync::Mutex::new(Instant::now()),
+                    })
+                }),
+        );
+        let permit = Arc::clone(&limit.permits)
+            .acquire_owned()
+            .await
+            .expect("origin semaphore is never closed");
+        let mut next_start = limit.next_start.lock().await;
+        tokio::time::sleep_until((*next_start).into()).await;
+        *next_start = Instant::now() + self.delay;
+        permit
+    }
+}
+
+/// Bounds on what is downloaded and parsed from a source.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct SourceLimits {
+    max_bytes: u64,
+    max_hosts: Option<usize>,
+}
+
+impl SourceLimits {
+    fn hosts_exceeded(self, url: &str) -> AppError {
+        AppError::LimitExceeded {
+            url: url.into(),
+            limit: format!("{} hosts", self.max_hosts.unwrap_or_default()),
+        }
+    }
+}
+
+/// Kind of document a source returned in place of a text list, such as a captive portal login
+/// page or CDN error page, going by its content type, then its first few bytes.
+fn unexpected_content(content_type: Option<&str>, body: &[u8]) -> Option<&'static str> {
+    if let Some(value) = content_type {
+        let media_type = value
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        match media_type.as_str() {
+            "text/html" | "application/xhtml+xml" => return Some("HTML"),
+            "application/json" => return Some("JSON"),
+            _ if media_type.ends_with("+json") => return Some("JSON"),
+            _ => {}
+        }
+    }
+
+    let start = body.strip_prefix(b"\xef\xbb\xbf").unwrap_or(body);
+    let start = &start[start
+        .iter()
+        .position(|val| !val.is_ascii_whitespace())
+        .unwrap_or(start.len())..];
+    let start = String::from_utf8_lossy(&start[..start.len().min(16)]).to_ascii_lowercase();
+    if ["<!doctype html", "<html", "<head", "<body"]
+        .iter()
+        .any(|val| start.starts_with(val))
+    {
+        Some("HTML")
+    } else if start.starts_with('{') {
+        // not `[`, which opens Adblock Plus style list headers
+        Some("JSON")
+    } else {
+        None
+    }
+}
+
+/// Checksum and signature to check a source's body against before parsing it.
+struct SourceVerification {
+    sha256_url: Option<String>,
+
+    /// Signature URL, and the public key to check it with
+    signature: Option<(String, PublicKey)>,
+}
+
+impl SourceVerification {
+    fn from_source(source: &BlocklistSource) -> Result<Option<Self>, AppError> {
+        if is_gpg_signature(source) {
+            return Err(AppError::Client {
+                message: format!(
+                    "source `{}` has a GPG signature or key, but only minisign signatures are supported",
+                    source.name
+                ),
+            });
+        }
+        let signature = match (&source.signature_url, &source.public_key) {
+            (Some(url), Some(key)) => {
+                let public_key =
+                    PublicKey::from_base64(key.trim()).map_err(|error| AppError::Client {
+                        message: format!(
+                            "invalid public key for source `{}`: {error}",
+                            source.name
+                        ),
+                    })?;
+                Some((url.clone(), public_key))
+            }
+            (Some(_), None) => {
+                return Err(AppError::Client {
+                    message: format!(
+                        "source `{}` has a signature URL, but no public key",
+                        source.name
+                    ),
+                })
+            }
+            (None, _) => None,
+        };
+        if source.sha256_url.is_none() && signature.is_none() {
+            return Ok(None);
+        }
+        Ok(Some(SourceVerification {
+            sha256_url: source.sha256_url.clone(),
+            signature,
+        }))
+    }
+}
+
+/// Whether a source is configured with a GPG signature or key, rather than a minisign one.
+fn is_gpg_signature(source: &BlocklistSource) -> bool {
+    let armored_key = source
+        .public_key
+        .as_ref()
+        .is_some_and(|val| val.contains("BEGIN PGP"));
+    let gpg_signature = source.signature_url.as_ref().is_some_and(|val| {
+        let path = val.split(['?', '#']).next().unwrap_or_default();
+        [".asc", ".gpg", ".pgp"]
+            .iter()
+            .any(|extension| path.to_ascii_lowercase().ends_with(extension))
+    });
+    armored_key || gpg_signature
+}
+
+/// Checks `body` against a checksum file, as written by `sha256sum`.  A file listing several
+/// checksums is searched for the one naming the file at `url`, while the checksum in a file
+/// listing just one is used whatever its name.
+fn verify_sha256(url: &str, body: &[u8], checksums: &str) -> Result<(), String> {
+    let file_name = url
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .rsplit('/')
+        .next()
+        .unwrap_or_default();
+    let entries: Vec<(&str, Option<&str>)> = checksums
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let checksum = fields.next()?;
+            Some((
+                checksum,
+                fields.next().map(|val| val.trim_start_matches('*')),
+            ))
+        })
+        .collect();
+    let expected = match entries.as_slice() {
+        [] => return Err(String::from("the checksum file lists no checksums")),
+        [(checksum, _)] => checksum,
+        _ => entries
+            .iter()
+            .find(|(_, name)| *name == Some(file_name))
+            .map(|(checksum, _)| checksum)
+            .ok_or_else(|| format!("no checksum for `{file_name}`"))?,
+    }
+    .to_ascii_lowercase();
+    let actual = format!("{:x}", Sha256::digest(body));
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!(
+            "SHA-256 checksum {actual} does not match {expected}"
+        ))
+    }
+}
+
+/// Checks `body` against a minisign signature.
+fn verify_signature(body: &[u8], signature: &str, public_key: &PublicKey) -> Result<(), String> {
+    let signature = Signature::decode(signature.trim())
+        .map_err(|error| format!("unable to read the signature: {error}"))?;
+    public_key
+        .verify(body, &signature, false)
+        .map_err(|error| format!("signature check failed: {error}"))
 }
 
+/// HTTP client for fetching blocklist sources.
 pub struct Client {
-    client: reqwest::Client,
+    http: reqwest::Client,
+
+    /// Extra request headers for each source URL configured with them
+    source_headers: HashMap<String, HeaderMap>,
+
+    /// Limits for sources not overriding them
+    default_limits: SourceLimits,
+
+    /// Most hosts, or networks of each IP list type, across all sources
+    max_total_hosts: Option<usize>,
+
+    /// Most bytes downloaded across all sources, including checksum and signature files
+    max_total_bytes: Option<u64>,
+    downloaded_bytes: AtomicU64,
+
+    /// Limits for each source URL overriding the defaults
+    source_limits: HashMap<String, SourceLimits>,
+
+    /// Checksums and signatures for each source URL configured with them
+    source_verifications: HashMap<String, SourceVerification>,
+
+    /// Sources fetched at once, across all origins.  A slot is only taken once the source's origin
+    /// allows the request, so sources queued on a busy origin never hold up other origins.
+    download_slots: Semaphore,
+    origin_limits: OriginLimits,
+    stats: Mutex<Vec<FetchStats>>,
 }
 
 impl Default for Client {
     fn default() -> Self {
-        Client {
-            client: reqwest::Client::new(),
-        }
+        Client::new(&Http::default(), &Blocklists::default())
+            .expect("default HTTP settings should build a client")
+    }
+}
+
+/// Request headers configured for a source.  Values are marked sensitive, as they often hold API
+/// tokens.
+fn source_header_map(source: &BlocklistSource) -> Result<HeaderMap, AppError> {
+    let mut result = HeaderMap::new();
+    for (name, value) in &source.headers {
+        let invalid = || AppError::Client {
+            message: format!("invalid header `{name}` for source `{}`", source.name),
+        };
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid())?;
+        let mut value = HeaderValue::from_str(value).map_err(|_| invalid())?;
+        value.set_sensitive(true);
+        result.insert(name, value);
     }
+    Ok(result)
 }
 
 impl Client {
+    /// Client using the timeouts, proxy, user agent, extra root certificates and download limits
+    /// configured in the `[http]` section, and sending the headers, applying the limits and
+    /// checking the checksums and signatures configured for each source.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the proxy URL, a root certificate file, a source header or a source
+    /// public key is invalid, or a source has a signature URL but no public key.
+    pub fn new(http: &Http, blocklists: &Blocklists) -> Result<Self, AppError> {
+        let mut builder = reqwest::Client::builder()
+            .connect_timeout(http.connect_timeout)
+            .read_timeout(http.read_timeout)
+            .user_agent(&http.user_agent);
+        if let Some(value) = &http.proxy {
+            let proxy = reqwest::Proxy::all(value).map_err(|error| AppError::Client {
+                message: format!("invalid proxy `{value}`: {error}"),
+            })?;
+            builder = builder.proxy(proxy);
+        }
+        for path in &http.ca_certificates {
+            let certificates = fs::read(path)
+                .map_err(|error| error.to_string())
+                .and_then(|val| {
+                    reqwest::Certificate::from_pem_bundle(&val).map_err(|error| error.to_string())
+                })
+                .map_err(|error| AppError::Client {
+                    message: format!("unable to load certificates `{}`: {error}", path.display()),
+                })?;
+            for certificate in certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        let client = builder.build().map_err(|error| AppError::Client {
+            message: error.to_string(),
+        })?;
+
+        let default_limits = SourceLimits {
+            max_bytes: http.max_bytes,
+            max_hosts: http.max_hosts,
+        };
+        let mut source_headers = HashMap::new();
+        let mut source_limits = HashMap::new();
+        let mut source_verifications = HashMap::new();
+        for source in &blocklists.sources {
+            if let Some(value) = SourceVerification::from_source(source)? {
+                source_verifications.insert(source.url.clone(), value);
+            }
+            if !source.headers.is_empty() {
+                source_headers.insert(source.url.clone(), source_header_map(source)?);
+            }
+            if source.max_bytes.is_some() || source.max_hosts.is_some() {
+                let limits = SourceLimits {
+                    max_bytes: source.max_bytes.unwrap_or(default_limits.max_bytes),
+                    max_hosts: source.max_hosts.or(default_limits.max_hosts),
+                };
+                source_limits.insert(source.url.clone(), limits);
+            }
+        }
+        Ok(Client {
+            http: client,
+            source_headers,
+            default_limits,
+            max_total_hosts: http.max_total_hosts,
+            max_total_bytes: http.max_total_bytes,
+            downloaded_bytes: AtomicU64::new(0),
+            source_limits,
+            source_verifications,
+            download_slots: Semaphore::new(http.concurrent_downloads.max(1)),
+            origin_limits: OriginLimits::new(http.per_host_concurrency, http.per_host_delay),
+            stats: Mutex::new(Vec::new()),
+        })
+    }
+
     fn handle_fetch_error(url: &str, error: &reqwest::Error) -> AppError {
-        log::error!("{error}");
+        log::error!(source_url = url; "{error}");
         if error.is_body() {
-            if let Some(hyper_error) = error.source().unwrap().downcast_ref::<hyper::Error>() {
+            let hyper_error = error
+                .source()
+                .and_then(|val| val.downcast_ref::<hyper::Error>());
+            if let Some(hyper_error) = hyper_error {
                 if hyper_error.is_incomplete_message() {
                     return AppError::IncompleteBody { url: url.into() };
                 }
             } else {
                 return AppError::FetchBody { url: url.into() };
-            };
+            }
         }
         if error.is_request() {
             return AppError::FetchRequest { url: url.into() };
@@ -58,70 +460,739 @@ impl Client {
         AppError::Fetch { url: url.into() }
     }
 
-    async fn get_html_body(&self, url: &str) -> Result<String, AppError> {
-        let response = match self.client.get(url).send().await {
+    /// Fetches the raw body of a source, recording its fetch stats.  Waits first if the source's
+    /// origin is at its concurrency limit, or was requested within the per-host delay.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the source cannot be fetched, or its body is larger than the source's
+    /// byte limit.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the stats lock is poisoned.
+    pub async fn get_html_body(&self, url: &str) -> Result<String, AppError> {
+        let _permits = self.acquire_download(url).await;
+        let start = Instant::now();
+        let result = self.fetch_body(url).await;
+        let duration = start.elapsed();
+        let duration_ms = u64::try_from(duration.as_millis()).unwrap_or(u64::MAX);
+        match &result {
+            Ok(value) => info!(
+                source_url = url, duration_ms, bytes = value.len();
+                "Fetched {url}"
+            ),
+            Err(error) => log::error!(
+                source_url = url, error_kind = error.kind(), duration_ms;
+                "{error}"
+            ),
+        }
+        self.stats.lock().unwrap().push(FetchStats {
+            url: url.to_string(),
+            duration,
+            bytes: result.as_ref().map_or(0, String::len),
+            error_kind: result.as_ref().err().map(AppError::kind),
+        });
+        result
+    }
+
+    /// Waits until the origin of `url` allows a request, then for a free download slot.
+    async fn acquire_download(&self, url: &str) -> (OwnedSemaphorePermit, SemaphorePermit<'_>) {
+        let origin_permit = self.origin_limits.acquire(url).await;
+        let download_permit = self
+            .download_slots
+            .acquire()
+            .await
+            .expect("download semaphore is never closed");
+        (origin_permit, download_permit)
+    }
+
+    /// Stats for each body fetched since the last call.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the stats lock is poisoned.
+    pub fn take_fetch_stats(&self) -> Vec<FetchStats> {
+        std::mem::take(&mut *self.stats.lock().unwrap())
+    }
+
+    fn limits(&self, url: &str) -> SourceLimits {
+        self.source_limits
+            .get(url)
+            .copied()
+            .unwrap_or(self.default_limits)
+    }
+
+    /// Reads a response body, in chunks, failing as soon as it grows past `max_bytes`, or takes the
+    /// bytes downloaded across all sources past their limit, or if it is an HTML or JSON document
+    /// rather than text.
+    async fn read_body(
+        &self,
+        url: &str,
+        mut response: reqwest::Response,
+        max_bytes: u64,
+    ) -> Result<Vec<u8>, AppError> {
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|val| val.to_str().ok())
+            .map(String::from);
+        if let Some(content) = unexpected_content(content_type.as_deref(), &[]) {
+            return Err(AppError::UnexpectedContent {
+                url: url.into(),
+                content,
+            });
+        }
+
+        let limit_exceeded = || AppError::LimitExceeded {
+            url: url.into(),
+            limit: format!("{max_bytes} bytes"),
+        };
+        if response.content_length().is_some_and(|val| val > max_bytes) {
+            return Err(limit_exceeded());
+        }
+        let mut body = Vec::new();
+        loop {
+            match response.chunk().await {
+                Ok(Some(value)) => {
+                    if (body.len() + value.len()) as u64 > max_bytes {
+                        return Err(limit_exceeded());
+                    }
+                    self.count_downloaded_bytes(url, value.len() as u64)?;
+                    body.extend_from_slice(&value);
+                }
+                Ok(None) => break,
+                Err(_) => return Err(AppError::FetchParse { url: url.into() }),
+            }
+        }
+        if let Some(content) = unexpected_content(None, &body) {
+            return Err(AppError::UnexpectedContent {
+                url: url.into(),
+                content,
+            });
+        }
+        Ok(body)
+    }
+
+    /// Adds `bytes` to the bytes downloaded across all sources, failing once they pass the limit.
+    fn count_downloaded_bytes(&self, url: &str, bytes: u64) -> Result<(), AppError> {
+        let total = self.downloaded_bytes.fetch_add(bytes, Ordering::Relaxed) + bytes;
+        match self.max_total_bytes {
+            Some(value) if total > value => Err(AppError::LimitExceeded {
+                url: url.into(),
+                limit: format!("{value} bytes across all sources"),
+            }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Fetches a checksum or signature file, within the byte limit of the source it belongs to.
+    /// These are small, and fetched alongside their source, so bypass the per-origin limits.
+    async fn fetch_verification_file(&self, url: &str, max_bytes: u64) -> Result<String, AppError> {
+        let response = match self.http.get(url).send().await {
             Ok(value) => value,
             Err(error) => return Err(Client::handle_fetch_error(url, &error)),
         };
+        match response.error_for_status() {
+            Ok(value) => {
+                let body = self.read_body(url, value, max_bytes).await?;
+                Ok(String::from_utf8_lossy(&body).into_owned())
+            }
+            Err(error) => Err(Client::handle_fetch_error(url, &error)),
+        }
+    }
 
-        match response.text().await {
-            Ok(value) => Ok(value),
-            Err(_) => Err(AppError::FetchParse { url: url.into() }),
+    /// Checks the source body against any checksum and signature configured for it.
+    async fn verify_body(&self, url: &str, body: &[u8]) -> Result<(), AppError> {
+        let Some(verification) = self.source_verifications.get(url) else {
+            return Ok(());
+        };
+        let max_bytes = self.limits(url).max_bytes;
+        let verification_error = |reason| AppError::Verification {
+            url: url.into(),
+            reason,
+        };
+        if let Some(value) = &verification.sha256_url {
+            let checksums = self.fetch_verification_file(value, max_bytes).await?;
+            verify_sha256(url, body, &checksums).map_err(verification_error)?;
         }
+        if let Some((signature_url, public_key)) = &verification.signature {
+            let signature = self
+                .fetch_verification_file(signature_url, max_bytes)
+                .await?;
+            verify_signature(body, &signature, public_key).map_err(verification_error)?;
+        }
+        Ok(())
     }
 
+    /// Fetches the body, failing on an HTTP error status, as soon as it grows past the source's byte
+    /// limit, if it is an HTML or JSON document rather than a text list, or if it fails
+    /// verification.
+    async fn fetch_body(&self, url: &str) -> Result<String, AppError> {
+        let mut request = self.http.get(url);
+        if let Some(value) = self.source_headers.get(url) {
+            request = request.headers(value.clone());
+        }
+        // an error page may be plain text, so would otherwise parse as an empty or junk list
+        let response = match request
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+        {
+            Ok(value) => value,
+            Err(error) => return Err(Client::handle_fetch_error(url, &error)),
+        };
+
+        let body = self
+            .read_body(url, response, self.limits(url).max_bytes)
+            .await?;
+        self.verify_body(url, &body).await?;
+        Ok(String::from_utf8_lossy(&body).into_owned())
+    }
+
+    /// Fetches and parses a list of domains, one per line.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the list cannot be fetched, or exceeds the source's limits.
     pub async fn domainlist(&self, url: &str) -> Result<HashSet<Host, RandomState>, AppError> {
         let mut result = HashSet::<Host, RandomState>::default();
-        info!("Fetching domainlist (stream): {url}");
+        info!(source_url = url; "Fetching domainlist (stream): {url}");
         let body = self.get_html_body(url).await?;
-        info!("Fetched {url}!");
-        parse_domainlist(&body, &mut result);
+        let limits = self.limits(url);
+        if !parse_domainlist(&body, &mut result, limits.max_hosts) {
+            return Err(limits.hosts_exceeded(url));
+        }
+        info!(source_url = url, hosts_parsed = result.len(); "Parsed {} hosts from {url}", result.len());
         Ok(result)
     }
 
+    /// Fetches and parses a hosts file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the hosts file cannot be fetched, or exceeds the source's limits.
     pub async fn hostsfile(&self, url: &str) -> Result<HashSet<Host, RandomState>, AppError> {
         let mut result = HashSet::<Host, RandomState>::default();
-        info!("Fetching domainlist (stream): {url}");
+        info!(source_url = url; "Fetching hosts file (stream): {url}");
         let body = self.get_html_body(url).await?;
-        info!("Fetched {url}!");
-        parse_hostfile(&body, &mut result);
+        let limits = self.limits(url);
+        if !parse_hostfile(&body, &mut result, limits.max_hosts) {
+            return Err(limits.hosts_exceeded(url));
+        }
+        info!(source_url = url, hosts_parsed = result.len(); "Parsed {} hosts from {url}", result.len());
+        Ok(result)
+    }
+
+    /// Fetches and parses a list of IP addresses and CIDR ranges.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the list cannot be fetched, or exceeds the source's limits.
+    pub async fn iplist(&self, url: &str) -> Result<BTreeSet<IpNet>, AppError> {
+        let mut result = BTreeSet::new();
+        info!(source_url = url; "Fetching iplist: {url}");
+        let body = self.get_html_body(url).await?;
+        let limits = self.limits(url);
+        if !parse_iplist(&body, &mut result, limits.max_hosts) {
+            return Err(limits.hosts_exceeded(url));
+        }
+        info!(source_url = url, networks_parsed = result.len(); "Parsed {} networks from {url}", result.len());
         Ok(result)
     }
 
+    /// Fetches and parses a source, according to its type.  IP lists list no hosts, so are not
+    /// fetched.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the source cannot be fetched.
     pub async fn fetch_set(
         &self,
         source: &Source<'_>,
     ) -> Result<HashSet<Host, RandomState>, AppError> {
-        let Source { url, source_type } = source;
+        let Source {
+            url, source_type, ..
+        } = source;
         match source_type {
             SourceType::DomainList => self.domainlist(url).await,
             SourceType::HostsFile => self.hostsfile(url).await,
+            SourceType::IpList | SourceType::ClientIpList => Ok(HashSet::default()),
         }
     }
 
-    fn fetch_futures<'a>(
-        &'a self,
-        sources: &'a [Source],
-    ) -> impl Stream<Item = impl Future<Output = Result<HashSet<Host, RandomState>, AppError>> + 'a>
-    {
-        futures::stream::iter(sources).map(move |val| self.fetch_set(val))
+    /// Fails once merging the source at `url` takes the count of hosts, or networks, past the
+    /// limit across all sources.
+    fn check_total_hosts(&self, url: &str, count: usize) -> Result<(), AppError> {
+        match self.max_total_hosts {
+            Some(value) if count > value => Err(AppError::LimitExceeded {
+                url: url.into(),
+                limit: format!("{value} hosts across all sources"),
+            }),
+            _ => Ok(()),
+        }
     }
 
+    /// Fetches every source, up to the configured number at a time, merging their hosts into
+    /// `provenance` along with the ID of each source listing them.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error, if any source cannot be fetched, or the merged hosts exceed the
+    /// limit across all sources.
     pub async fn domainlists(
         &self,
         sources: &[Source<'_>],
-        set: &mut HashSet<Host, RandomState>,
+        provenance: &mut Provenance,
     ) -> Result<(), AppError> {
-        let concurrent_downloads = 3;
-        let mut result_sets = self
-            .fetch_futures(sources)
-            .buffer_unordered(concurrent_downloads)
-            .collect::<Vec<Result<HashSet<Host, RandomState>, AppError>>>()
-            .await;
-        for result_set in &mut result_sets {
-            let set_values = result_set.as_mut().unwrap().drain();
-            set.extend(set_values);
+        // each fetch waits for its origin, then a download slot, in `get_html_body`
+        let result_sets =
+            futures::future::join_all(sources.iter().map(|val| self.fetch_set(val))).await;
+
+        // merge in source order, so each host's source IDs are ascending
+        for (source_id, result_set) in result_sets.into_iter().enumerate() {
+            for host in result_set? {
+                provenance.entry(host).or_default().push(source_id);
+            }
+            self.check_total_hosts(sources[source_id].url, provenance.len())?;
         }
         Ok(())
     }
+
+    /// Fetches every source of type `source_type`, up to the configured number at a time, merging
+    /// their IP addresses and CIDR ranges.  Returns the merged networks, with overlapping ranges
+    /// aggregated.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first error, if any source cannot be fetched, or the merged networks exceed
+    /// the limit across all sources.
+    pub async fn iplists(
+        &self,
+        sources: &[Source<'_>],
+        source_type: SourceType,
+    ) -> Result<Vec<IpNet>, AppError> {
+        let sources: Vec<&Source> = sources
+            .iter()
+            .filter(|val| val.source_type == source_type)
+            .collect();
+        let result_sets =
+            futures::future::join_all(sources.iter().map(|val| self.iplist(val.url))).await;
+
+        let mut networks = BTreeSet::new();
+        for (source, result_set) in sources.iter().zip(result_sets) {
+            networks.extend(result_set?);
+            self.check_total_hosts(source.url, networks.len())?;
+        }
+        Ok(IpNet::aggregate(&networks.into_iter().collect()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        unexpected_content, verify_sha256, verify_signature, AppError, Client, OriginLimits,
+        Provenance,
+    };
+    use crate::{
+        file_system::{Blocklists, Http},
+        Source, SourceType,
+    };
+    use minisign_verify::PublicKey;
+    use std::time::{Duration, Instant};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        task::JoinHandle,
+    };
+
+    /// Serves `response` to a single request, returning the URL served and a handle giving the
+    /// request received, lower-cased.
+    async fn serve_once(response: &'static [u8]) -> (String, JoinHandle<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap(); // DevSkim: ignore DS162092 - use of local host IP is in test
+        let url = format!("http://{}/hosts.txt", listener.local_addr().unwrap());
+        let server = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![0u8; 4096];
+            let length = stream.read(&mut request).await.unwrap();
+            stream.write_all(response).await.unwrap();
+            String::from_utf8_lossy(&request[..length]).to_lowercase()
+        });
+        (url, server)
+    }
+
+    #[tokio::test]
+    async fn origin_limits_space_and_limit_requests_to_same_origin() {
+        // arrange
+        let limits = OriginLimits::new(1, Duration::from_millis(200));
+        let start = Instant::now();
+
+        // act
+        let first_permit = limits
+            .acquire("https://v.firebog.net/hosts/AdguardDNS.txt")
+            .await;
+        let other_origin = tokio::time::timeout(
+            Duration::from_millis(50),
+            limits.acquire("https://pgl.yoyo.org/adservers/serverlist.php"),
+        )
+        .await;
+        let blocked = tokio::time::timeout(
+            Duration::from_millis(50),
+            limits.acquire("https://v.firebog.net/hosts/Easyprivacy.txt"),
+        )
+        .await;
+        drop(first_permit);
+        let _second_permit = limits
+            .acquire("https://v.firebog.net/hosts/Easyprivacy.txt")
+            .await;
+
+        // assert
+        assert!(other_origin.is_ok());
+        assert!(blocked.is_err());
+        assert!(start.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[tokio::test]
+    async fn acquire_download_leaves_slots_free_while_waiting_on_origin() {
+        // arrange
+        let http = Http {
+            concurrent_downloads: 2,
+            per_host_concurrency: 1,
+            ..Http::default()
+        };
+        let client = Client::new(&http, &Blocklists::default()).unwrap();
+        let _first_permits = client
+            .acquire_download("https://v.firebog.net/hosts/AdguardDNS.txt")
+            .await;
+
+        // act
+        let mut queued =
+            Box::pin(client.acquire_download("https://v.firebog.net/hosts/Easyprivacy.txt"));
+        let queued_result = tokio::time::timeout(Duration::from_millis(50), &mut queued).await;
+        let other_origin = tokio::time::timeout(
+            Duration::from_millis(50),
+            client.acquire_download("https://pgl.yoyo.org/adservers/serverlist.php"),
+        )
+        .await;
+
+        // assert
+        assert!(queued_result.is_err());
+        assert!(other_origin.is_ok());
+    }
+
+    #[tokio::test]
+    async fn get_html_body_sends_user_agent_and_source_headers() {
+        // arrange
+        let (url, server) = serve_once(
+            b"HTTP/1.1 200 OK\r\ncontent-length: 12\r\nconnection: close\r\n\r\nexample.com\n",
+        )
+        .await;
+        let blocklists: Blocklists = toml::from_str(&format!(
+            "[[sources]]\nname = \"feed\"\nurl = \"{url}\"\ntype = \"domain_list\"\nheaders = {{ Authorization = \"Bearer token\" }}\n"
+        ))
+        .unwrap();
+        let http = Http {
+            user_agent: String::from("test-agent/1.0"),
+            ..Http::default()
+        };
+        let client = Client::new(&http, &blocklists).unwrap();
+
+        // act
+        let result = client.get_html_body(&url).await.unwrap();
+
+        // assert
+        assert_eq!(result, "example.com\n");
+        let request = server.await.unwrap();
+        assert!(request.contains("user-agent: test-agent/1.0\r\n"));
+        assert!(request.contains("authorization: bearer token\r\n"));
+    }
+
+    #[test]
+    fn unexpected_content_detects_html_and_json() {
+        // arrange
+        let adblock_list = b"[Adblock Plus 2.0]\n||ads.example.com^\n";
+
+        // act
+        let result_0 = unexpected_content(Some("text/html; charset=utf-8"), b"");
+        let result_1 = unexpected_content(Some("application/problem+json"), b"");
+        let result_2 = unexpected_content(Some("text/plain"), b"\xef\xbb\xbf\n <!DOCTYPE html>");
+        let result_3 = unexpected_content(None, b"{\"error\": \"rate limited\"}");
+        let result_4 = unexpected_content(Some("text/plain"), adblock_list);
+        let result_5 = unexpected_content(None, b"0.0.0.0 ads.example.com\n");
+
+        // assert
+        assert_eq!(result_0, Some("HTML"));
+        assert_eq!(result_1, Some("JSON"));
+        assert_eq!(result_2, Some("HTML"));
+        assert_eq!(result_3, Some("JSON"));
+        assert_eq!(result_4, None);
+        assert_eq!(result_5, None);
+    }
+
+    #[test]
+    fn verify_sha256_matches_named_checksum() {
+        // arrange
+        let url = "https://example.com/lists/ads.txt?format=plain";
+        let checksums = "\
+            0000000000000000000000000000000000000000000000000000000000000000  tracking.txt
+            9F86D081884C7D659A2FEAA0C55AD015A3BF4F1B2B0B822CD15D6C15B0F00A08 *ads.txt\n";
+
+        // act
+        let result_0 = verify_sha256(url, b"test", checksums);
+        let result_1 = verify_sha256(url, b"tampered", checksums);
+
+        // assert
+        assert_eq!(result_0, Ok(()));
+        assert!(result_1.is_err());
+    }
+
+    #[test]
+    fn verify_sha256_needs_named_checksum_among_several() {
+        // arrange
+        let url = "https://example.com/lists/ads.txt";
+        let several = "\
+            0000000000000000000000000000000000000000000000000000000000000000  tracking.txt
+            9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08  malware.txt\n";
+        let single =
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08  ads-latest.txt\n";
+
+        // act
+        let result_0 = verify_sha256(url, b"test", several);
+        let result_1 = verify_sha256(url, b"test", single);
+
+        // assert
+        assert_eq!(result_0, Err(String::from("no checksum for `ads.txt`")));
+        assert_eq!(result_1, Ok(()));
+    }
+
+    #[test]
+    fn verify_signature_checks_minisign_signature() {
+        // arrange
+        let public_key =
+            PublicKey::from_base64("RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3")
+                .unwrap();
+        let signature = "untrusted comment: signature from minisign secret key
+RUQf6LRCGA9i559r3g7V1qNyJDApGip8MfqcadIgT9CuhV3EMhHoN1mGTkUidF/z7SrlQgXdy8ofjb7bNJJylDOocrCo8KLzZwo=
+trusted comment: timestamp:1633700835\tfile:test\tprehashed
+wLMDjy9FLAuxZ3q4NlEvkgtyhrr0gtTu6KC4KBJdITbbOeAi1zBIYo0v4iTgt8jJpIidRJnp94ABQkJAgAooBQ==
+";
+
+        // act
+        let result_0 = verify_signature(b"test", signature, &public_key);
+        let result_1 = verify_signature(b"tampered", signature, &public_key);
+
+        // assert
+        assert_eq!(result_0, Ok(()));
+        assert!(result_1.is_err());
+    }
+
+    #[test]
+    fn client_new_rejects_gpg_signature() {
+        // arrange
+        let blocklists: Blocklists = toml::from_str(
+            "[[sources]]\nname = \"ads\"\nurl = \"https://example.com/ads.txt\"\ntype = \"domain_list\"\nsignature_url = \"https://example.com/ads.txt.asc\"\npublic_key = \"RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3\"\n",
+        )
+        .unwrap();
+
+        // act
+        let result = Client::new(&Http::default(), &blocklists);
+
+        // assert
+        assert!(matches!(result, Err(AppError::Client { .. })));
+    }
+
+    #[tokio::test]
+    async fn get_html_body_rejects_html_checksum_file() {
+        // arrange
+        let (url, _server) =
+            serve_once(b"HTTP/1.1 200 OK\r\ncontent-length: 5\r\nconnection: close\r\n\r\ntest\n")
+                .await;
+        let (sha256_url, _sha256_server) = serve_once(
+            b"HTTP/1.1 200 OK\r\ncontent-type: text/html\r\ncontent-length: 15\r\nconnection: close\r\n\r\n<html></html>\r\n",
+        )
+        .await;
+        let blocklists: Blocklists = toml::from_str(&format!(
+            "[[sources]]\nname = \"feed\"\nurl = \"{url}\"\ntype = \"domain_list\"\nsha256_url = \"{sha256_url}\"\n"
+        ))
+        .unwrap();
+        let client = Client::new(&Http::default(), &blocklists).unwrap();
+
+        // act
+        let result = client.get_html_body(&url).await;
+
+        // assert
+        assert!(matches!(
+            result,
+            Err(AppError::UnexpectedContent { url, content }) if url == sha256_url && content == "HTML"
+        ));
+    }
+
+    #[tokio::test]
+    async fn get_html_body_rejects_error_status_with_text_body() {
+        // arrange
+        let (url, _server) = serve_once(
+            b"HTTP/1.1 404 Not Found\r\ncontent-type: text/plain\r\ncontent-length: 10\r\nconnection: close\r\n\r\nnot found\n",
+        )
+        .await;
+        let client = Client::default();
+
+        // act
+        let result = client.get_html_body(&url).await;
+
+        // assert
+        assert!(matches!(result, Err(AppError::Fetch { .. })));
+    }
+
+    #[tokio::test]
+    async fn get_html_body_rejects_html_error_page() {
+        // arrange
+        let (url, _server) = serve_once(
+            b"HTTP/1.1 200 OK\r\ncontent-type: text/html\r\ncontent-length: 15\r\nconnection: close\r\n\r\n<html></html>\r\n",
+        )
+        .await;
+        let client = Client::default();
+
+        // act
+        let result = client.get_html_body(&url).await;
+
+        // assert
+        assert!(matches!(
+            result,
+            Err(AppError::UnexpectedContent { content, .. }) if content == "HTML"
+        ));
+    }
+
+    #[tokio::test]
+    async fn get_html_body_stops_at_source_byte_limit() {
+        // arrange
+        let (url, _server) = serve_once(
+            b"HTTP/1.1 200 OK\r\nconnection: close\r\n\r\nads.example.com\ntracker.example.com\n",
+        )
+        .await;
+        let blocklists: Blocklists = toml::from_str(&format!(
+            "[[sources]]\nname = \"feed\"\nurl = \"{url}\"\ntype = \"domain_list\"\nmax_bytes = 16\n"
+        ))
+        .unwrap();
+        let client = Client::new(&Http::default(), &blocklists).unwrap();
+
+        // act
+        let result = client.get_html_body(&url).await;
+
+        // assert
+        assert!(matches!(
+            result,
+            Err(AppError::LimitExceeded { limit, .. }) if limit == "16 bytes"
+        ));
+    }
+
+    #[tokio::test]
+    async fn domainlist_fails_past_host_limit() {
+        // arrange
+        let (url, _server) = serve_once(
+            b"HTTP/1.1 200 OK\r\ncontent-length: 36\r\nconnection: close\r\n\r\nads.example.com\ntracker.example.com\n",
+        )
+        .await;
+        let http = Http {
+            max_hosts: Some(1),
+            ..Http::default()
+        };
+        let blocklists: Blocklists = toml::from_str("").unwrap();
+        let client = Client::new(&http, &blocklists).unwrap();
+
+        // act
+        let result = client.domainlist(&url).await;
+
+        // assert
+        assert!(matches!(
+            result,
+            Err(AppError::LimitExceeded { limit, .. }) if limit == "1 hosts"
+        ));
+    }
+
+    /// Serves two domain lists, of two hosts each, returning them as sources.
+    async fn two_host_sources() -> (Vec<String>, Vec<JoinHandle<String>>) {
+        let (url_0, server_0) = serve_once(
+            b"HTTP/1.1 200 OK\r\ncontent-length: 32\r\nconnection: close\r\n\r\nads.example.com\nads.example.net\n",
+        )
+        .await;
+        let (url_1, server_1) = serve_once(
+            b"HTTP/1.1 200 OK\r\ncontent-length: 40\r\nconnection: close\r\n\r\ntracker.example.com\ntracker.example.net\n",
+        )
+        .await;
+        (vec![url_0, url_1], vec![server_0, server_1])
+    }
+
+    fn domain_sources(urls: &[String]) -> Vec<Source<'_>> {
+        urls.iter()
+            .map(|val| Source {
+                name: String::from("local"),
+                url: val,
+                source_type: SourceType::DomainList,
+                category: None,
+                weight: 1,
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn domainlists_caps_total_hosts_apart_from_source_limit() {
+        // arrange
+        let (urls_0, _servers_0) = two_host_sources().await;
+        let (urls_1, _servers_1) = two_host_sources().await;
+        let http_0 = Http {
+            max_hosts: Some(2),
+            max_total_hosts: Some(3),
+            ..Http::default()
+        };
+        let http_1 = Http {
+            max_hosts: Some(2),
+            max_total_hosts: Some(4),
+            ..Http::default()
+        };
+        let client_0 = Client::new(&http_0, &Blocklists::default()).unwrap();
+        let client_1 = Client::new(&http_1, &Blocklists::default()).unwrap();
+        let mut provenance_0 = Provenance::default();
+        let mut provenance_1 = Provenance::default();
+
+        // act
+        let result_0 = client_0
+            .domainlists(&domain_sources(&urls_0), &mut provenance_0)
+            .await;
+        let result_1 = client_1
+            .domainlists(&domain_sources(&urls_1), &mut provenance_1)
+            .await;
+
+        // assert
+        assert!(matches!(
+            result_0,
+            Err(AppError::LimitExceeded { url, limit })
+                if url == urls_0[1] && limit == "3 hosts across all sources"
+        ));
+        assert!(result_1.is_ok());
+        assert_eq!(provenance_1.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn domainlists_caps_total_bytes_apart_from_source_limit() {
+        // arrange
+        let (urls, _servers) = two_host_sources().await;
+        let http = Http {
+            max_bytes: 40,
+            max_total_bytes: Some(50),
+            ..Http::default()
+        };
+        let client = Client::new(&http, &Blocklists::default()).unwrap();
+        let mut provenance = Provenance::default();
+
+        // act
+        let result = client
+            .domainlists(&domain_sources(&urls), &mut provenance)
+            .await;
+
+        // assert
+        assert!(matches!(
+            result,
+            Err(AppError::LimitExceeded { limit, .. }) if limit == "50 bytes across all sources"
+        ));
+    }
 }
@@ -1,37 +1,603 @@
 use ahash::RandomState;
-use askama::Template;
 use humansize::{format_size, DECIMAL};
-use log::{error, info};
-use serde::Deserialize;
+use ipnet::IpNet;
+use log::{error, info, warn};
+use serde::{Deserialize, Deserializer};
 use std::{
-    collections::HashSet,
-    fs::{self, File},
-    io::Write,
+    collections::{HashMap, HashSet},
+    fs, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     path::{Path, PathBuf},
+    time::Duration,
 };
 use url::Host;
 
-use crate::parse::domainlist as parse_domainlist;
+use crate::{
+    output::OutputFormat,
+    parse::domainlist as parse_domainlist,
+    pattern::NamePattern,
+    pihole::{read_gravity_domains, write_gravity_database},
+    validate::Strictness,
+    Blocklist, SourceType,
+};
 
 #[derive(Deserialize)]
 pub struct Blocklists {
+    #[serde(default)]
     pub hosts_file_blocklist_urls: Vec<String>,
+    #[serde(default)]
     pub domain_blocklist_urls: Vec<String>,
+
+    /// Sources configured with a name, from `[[blocklists.sources]]` tables
+    #[serde(default)]
+    pub sources: Vec<BlocklistSource>,
+
+    /// Total weight of sources which must list a host for it to be blocked, unless the source
+    /// category sets its own minimum
+    #[serde(default = "default_min_sources")]
+    pub min_sources: u32,
+
+    /// Settings for each source category, from `[blocklists.categories.<name>]` tables
+    #[serde(default)]
+    pub categories: HashMap<String, Category>,
+}
+
+impl Default for Blocklists {
+    fn default() -> Self {
+        Blocklists {
+            hosts_file_blocklist_urls: Vec::new(),
+            domain_blocklist_urls: Vec::new(),
+            sources: Vec::new(),
+            min_sources: default_min_sources(),
+            categories: HashMap::new(),
+        }
+    }
+}
+
+fn default_min_sources() -> u32 {
+    1
+}
+
+fn default_source_weight() -> u32 {
+    1
+}
+
+#[derive(Deserialize)]
+pub struct BlocklistSource {
+    /// Short name used in provenance comments, `yoyo` for example
+    pub name: String,
+    pub url: String,
+    #[serde(rename = "type")]
+    pub source_type: SourceType,
+
+    /// Category, such as `ads` or `malware`, selecting the minimum agreement needed
+    pub category: Option<String>,
+
+    /// Count towards `min_sources` for each host listed.  A source with weight at least
+    /// `min_sources` blocks hosts alone
+    #[serde(default = "default_source_weight")]
+    pub weight: u32,
+
+    /// Extra request headers, such as an API token for a commercial feed
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+
+    /// Largest body to download, in bytes, overriding `max_bytes` in the `[http]` section
+    pub max_bytes: Option<u64>,
+
+    /// Most hosts or networks to parse, overriding `max_hosts` in the `[http]` section.  Parsing
+    /// stops as soon as the source lists more
+    pub max_hosts: Option<usize>,
+
+    /// URL of the list's SHA-256 checksum, in `sha256sum` output format
+    pub sha256_url: Option<String>,
+
+    /// URL of the list's minisign signature, checked with `public_key`.  GPG signatures are not
+    /// supported, and fail the config
+    pub signature_url: Option<String>,
+
+    /// Minisign public key, the base64 line of the provider's `minisign.pub` file
+    pub public_key: Option<String>,
 }
 
 #[derive(Deserialize)]
-struct Config {
-    blocklists: Blocklists,
+pub struct Category {
+    /// Total weight of sources which must list a host in this category, overriding the global
+    /// `min_sources`
+    pub min_sources: u32,
 }
 
-pub fn get_blocklists_from_config_file<P: AsRef<Path>>(config_file_path: P) -> Blocklists {
-    let config_file_content =
-        fs::read_to_string(config_file_path).expect("Unable to open or read config file");
-    let config: Config = toml::from_str(&config_file_content).expect("Unable to parse TOML config");
+fn default_serve_listen() -> SocketAddr {
+    SocketAddr::from((Ipv4Addr::LOCALHOST, 53))
+}
+
+fn default_serve_ttl() -> u32 {
+    60
+}
+
+#[derive(Deserialize)]
+pub struct Serve {
+    #[serde(default = "default_serve_listen")]
+    pub listen: SocketAddr,
+    pub upstream: SocketAddr,
+    pub sinkhole_ipv4: Option<Ipv4Addr>,
+    pub sinkhole_ipv6: Option<Ipv6Addr>,
+    #[serde(default = "default_serve_ttl")]
+    pub ttl: u32,
+}
+
+/// Parses human-friendly durations, such as `6h` or `1h 30m`.
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    humantime::parse_duration(&value).map_err(serde::de::Error::custom)
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct Daemon {
+    /// Time between successive rebuilds
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub interval: Duration,
+
+    /// Upper bound on the random delay added to each interval, so that many instances do not hit
+    /// upstream servers at the same moment
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub jitter: Duration,
+}
+
+impl Default for Daemon {
+    fn default() -> Self {
+        Daemon {
+            interval: Duration::from_secs(6 * 60 * 60),
+            jitter: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+fn default_output_directory() -> PathBuf {
+    PathBuf::from(".")
+}
+
+fn default_output_formats() -> Vec<OutputFormat> {
+    vec![OutputFormat::Rpz]
+}
 
-    let Config { blocklists } = config;
+#[derive(Clone, Deserialize)]
+pub struct Output {
+    #[serde(default = "default_output_directory")]
+    pub directory: PathBuf,
+    #[serde(default = "default_output_formats")]
+    pub formats: Vec<OutputFormat>,
 
-    blocklists
+    /// Precede each RPZ record and adlist domain with a comment naming the sources which list it
+    #[serde(default)]
+    pub source_comments: bool,
+
+    /// Pi-hole `gravity.db` to write blocked domains to, relative to the output directory
+    pub pihole_gravity_database: Option<PathBuf>,
+}
+
+impl Default for Output {
+    fn default() -> Self {
+        Output {
+            directory: default_output_directory(),
+            formats: default_output_formats(),
+            source_comments: false,
+            pihole_gravity_database: None,
+        }
+    }
+}
+
+impl Output {
+    #[must_use]
+    pub fn path(&self, format: OutputFormat) -> PathBuf {
+        self.directory.join(format.file_name())
+    }
+}
+
+fn default_serve_http_listen() -> SocketAddr {
+    SocketAddr::from((Ipv4Addr::LOCALHOST, 8080))
+}
+
+#[derive(Deserialize)]
+pub struct ServeHttp {
+    #[serde(default = "default_serve_http_listen")]
+    pub listen: SocketAddr,
+}
+
+impl Default for ServeHttp {
+    fn default() -> Self {
+        ServeHttp {
+            listen: default_serve_http_listen(),
+        }
+    }
+}
+
+fn default_zone_transfer_snapshot_directory() -> PathBuf {
+    PathBuf::from("zone-snapshots")
+}
+
+fn default_zone_transfer_keep_snapshots() -> usize {
+    24
+}
+
+fn default_zone_transfer_allow_transfer() -> Vec<IpNet> {
+    vec![
+        IpNet::from(IpAddr::from(Ipv4Addr::LOCALHOST)),
+        IpNet::from(IpAddr::from(Ipv6Addr::LOCALHOST)),
+    ]
+}
+
+#[derive(Deserialize)]
+pub struct ZoneTransfer {
+    #[serde(default = "default_serve_listen")]
+    pub listen: SocketAddr,
+
+    /// Zone name, matching the RPZ zone configured on secondaries, `rpz.local` for example
+    pub origin: String,
+    #[serde(default = "default_serve_ttl")]
+    pub ttl: u32,
+    #[serde(default = "default_zone_transfer_snapshot_directory")]
+    pub snapshot_directory: PathBuf,
+
+    /// Number of past zone versions kept for computing incremental transfers
+    #[serde(default = "default_zone_transfer_keep_snapshots")]
+    pub keep_snapshots: usize,
+
+    /// Secondaries sent `NOTIFY` by `serve-zone` once it loads a new serial
+    #[serde(default)]
+    pub notify: Vec<SocketAddr>,
+
+    /// Networks of secondaries allowed to transfer the zone with `AXFR` or `IXFR`, besides the
+    /// `notify` secondaries.  Only the local host, by default.
+    #[serde(default = "default_zone_transfer_allow_transfer")]
+    pub allow_transfer: Vec<IpNet>,
+}
+
+impl ZoneTransfer {
+    /// Networks allowed to transfer the zone, including the address of each `notify` secondary.
+    #[must_use]
+    pub fn transfer_networks(&self) -> Vec<IpNet> {
+        self.allow_transfer
+            .iter()
+            .copied()
+            .chain(self.notify.iter().map(|val| IpNet::from(val.ip())))
+            .collect()
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct Hooks {
+    /// Shell commands run, in order, once after a build changes any output files
+    pub post_write: Vec<String>,
+}
+
+fn default_query_cache_directory() -> PathBuf {
+    PathBuf::from("source-cache")
+}
+
+fn default_query_max_cache_age() -> Duration {
+    Duration::from_secs(60 * 60)
+}
+
+#[derive(Deserialize)]
+pub struct Query {
+    /// Directory holding source bodies fetched by `query`
+    #[serde(default = "default_query_cache_directory")]
+    pub cache_directory: PathBuf,
+
+    /// Cached source bodies older than this are fetched again
+    #[serde(
+        default = "default_query_max_cache_age",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub max_cache_age: Duration,
+}
+
+impl Default for Query {
+    fn default() -> Self {
+        Query {
+            cache_directory: default_query_cache_directory(),
+            max_cache_age: default_query_max_cache_age(),
+        }
+    }
+}
+
+fn default_http_connect_timeout() -> Duration {
+    Duration::from_secs(10)
+}
+
+fn default_http_read_timeout() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_http_concurrent_downloads() -> usize {
+    3
+}
+
+fn default_http_per_host_concurrency() -> usize {
+    1
+}
+
+fn default_http_max_bytes() -> u64 {
+    256 * 1024 * 1024
+}
+
+fn default_http_user_agent() -> String {
+    format!("blocklist-generator/{}", env!("CARGO_PKG_VERSION"))
+}
+
+#[derive(Deserialize)]
+pub struct Http {
+    /// Time allowed to connect to a source server
+    #[serde(
+        default = "default_http_connect_timeout",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub connect_timeout: Duration,
+
+    /// Time allowed between reads of a response, so a stalled server fails the fetch rather than
+    /// the whole run hanging
+    #[serde(
+        default = "default_http_read_timeout",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub read_timeout: Duration,
+
+    /// Proxy for every request, as an `http://`, `https://`, `socks5://` or `socks5h://` URL.
+    /// Without one, the `HTTP_PROXY` and `HTTPS_PROXY` environment variables apply
+    pub proxy: Option<String>,
+
+    #[serde(default = "default_http_user_agent")]
+    pub user_agent: String,
+
+    /// PEM files of extra root certificates to trust, for an intercepting proxy or private mirror
+    #[serde(default)]
+    pub ca_certificates: Vec<PathBuf>,
+
+    /// Sources fetched at once
+    #[serde(default = "default_http_concurrent_downloads")]
+    pub concurrent_downloads: usize,
+
+    /// Sources fetched at once from any one origin, such as `https://v.firebog.net`
+    #[serde(default = "default_http_per_host_concurrency")]
+    pub per_host_concurrency: usize,
+
+    /// Minimum time between starting one request to an origin and starting the next
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub per_host_delay: Duration,
+
+    /// Largest body to download from each source, in bytes.  A larger body fails the source,
+    /// rather than exhausting memory
+    #[serde(default = "default_http_max_bytes")]
+    pub max_bytes: u64,
+
+    /// Most hosts or networks to parse from each source, or no limit if unset
+    pub max_hosts: Option<usize>,
+
+    /// Most hosts to merge across all sources, or no limit if unset.  Networks are counted
+    /// separately for each IP list type
+    pub max_total_hosts: Option<usize>,
+
+    /// Most bytes to download across all sources, or no limit if unset
+    pub max_total_bytes: Option<u64>,
+}
+
+impl Default for Http {
+    fn default() -> Self {
+        Http {
+            connect_timeout: default_http_connect_timeout(),
+            read_timeout: default_http_read_timeout(),
+            proxy: None,
+            user_agent: default_http_user_agent(),
+            ca_certificates: Vec::new(),
+            concurrent_downloads: default_http_concurrent_downloads(),
+            per_host_concurrency: default_http_per_host_concurrency(),
+            per_host_delay: Duration::ZERO,
+            max_bytes: default_http_max_bytes(),
+            max_hosts: None,
+            max_total_hosts: None,
+            max_total_bytes: None,
+        }
+    }
+}
+
+/// Command line options overriding the `[http]` section of the config file.
+#[derive(clap::Args, Clone, Debug, Default)]
+pub struct HttpOverrides {
+    /// Proxy URL for fetching sources, `socks5h://127.0.0.1:1080` for example
+    #[clap(long)]
+    pub proxy: Option<String>,
+
+    /// User-Agent header sent when fetching sources
+    #[clap(long)]
+    pub user_agent: Option<String>,
+
+    /// Time allowed to connect to a source server, `10s` for example
+    #[clap(long)]
+    pub connect_timeout: Option<humantime::Duration>,
+
+    /// Time allowed between reads of a source response, `60s` for example
+    #[clap(long)]
+    pub read_timeout: Option<humantime::Duration>,
+}
+
+impl HttpOverrides {
+    /// Replaces settings in `http` with any given on the command line.
+    pub fn apply(&self, http: &mut Http) {
+        if let Some(value) = &self.proxy {
+            http.proxy = Some(value.clone());
+        }
+        if let Some(value) = &self.user_agent {
+            http.user_agent.clone_from(value);
+        }
+        if let Some(value) = self.connect_timeout {
+            http.connect_timeout = *value;
+        }
+        if let Some(value) = self.read_timeout {
+            http.read_timeout = *value;
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct History {
+    /// Database recording when each host entered and left the blocklist
+    pub database: PathBuf,
+}
+
+#[derive(Deserialize)]
+pub struct Metrics {
+    /// Prometheus metrics file, written after each build, for the node exporter textfile
+    /// collector.  `serve-http` and `serve-zone` include these metrics on their `/metrics`
+    /// endpoints
+    pub textfile: Option<PathBuf>,
+
+    /// Address serving `/metrics` from `serve`, `serve-zone` and `daemon`.  `serve-http` serves
+    /// `/metrics` on its own address
+    pub listen: Option<SocketAddr>,
+}
+
+fn default_retention_state_file() -> PathBuf {
+    PathBuf::from("retention-state.tsv")
+}
+
+#[derive(Deserialize)]
+pub struct Retention {
+    /// Hosts which drop out of every source stay blocked for this long after they were last
+    /// listed, so flapping upstream lists do not churn the output
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub grace_period: Duration,
+
+    /// File recording when each host was last listed
+    #[serde(default = "default_retention_state_file")]
+    pub state_file: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum PublicSuffixAction {
+    /// Remove public suffixes from the blocklist, logging a warning
+    #[default]
+    Reject,
+
+    /// Keep public suffixes in the blocklist, logging a warning
+    Warn,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct PublicSuffixes {
+    /// Local copy of the Public Suffix List, used instead of the bundled copy
+    pub list: Option<PathBuf>,
+
+    /// Handling of blocked names which are public suffixes, such as `co.uk` or `github.io`
+    pub action: PublicSuffixAction,
+
+    /// Print the registrable domains with the most blocked names after each build
+    pub group_stats: bool,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+pub struct Validation {
+    /// How closely blocked names must follow DNS hostname rules
+    pub strictness: Strictness,
+
+    /// File listing each rejected name, with the reason and the sources listing it
+    pub rejected_report: Option<PathBuf>,
+}
+
+fn default_firewall_timeout() -> Duration {
+    Duration::from_secs(2)
+}
+
+fn default_firewall_concurrency() -> usize {
+    32
+}
+
+#[derive(Deserialize)]
+pub struct Firewall {
+    /// Resolver used to look up the current `A` and `AAAA` records of blocked domains, for the
+    /// `resolved_ipv4` and `resolved_ipv6` firewall sets.  Domains are not resolved without one
+    pub resolver: Option<SocketAddr>,
+
+    /// Time to wait for each lookup
+    #[serde(
+        default = "default_firewall_timeout",
+        deserialize_with = "deserialize_duration"
+    )]
+    pub timeout: Duration,
+
+    /// Lookups in flight at once
+    #[serde(default = "default_firewall_concurrency")]
+    pub concurrency: usize,
+}
+
+impl Default for Firewall {
+    fn default() -> Self {
+        Firewall {
+            resolver: None,
+            timeout: default_firewall_timeout(),
+            concurrency: default_firewall_concurrency(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Config {
+    pub blocklists: Blocklists,
+    pub serve: Option<Serve>,
+    #[serde(default)]
+    pub daemon: Daemon,
+    #[serde(default)]
+    pub firewall: Firewall,
+    pub history: Option<History>,
+    #[serde(default)]
+    pub hooks: Hooks,
+    #[serde(default)]
+    pub http: Http,
+    pub metrics: Option<Metrics>,
+    #[serde(default)]
+    pub output: Output,
+    #[serde(default)]
+    pub public_suffixes: PublicSuffixes,
+    #[serde(default)]
+    pub query: Query,
+    pub retention: Option<Retention>,
+    #[serde(default)]
+    pub serve_http: ServeHttp,
+    #[serde(default)]
+    pub validation: Validation,
+    pub zone_transfer: Option<ZoneTransfer>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ConfigError {
+    #[error("Unable to open or read config file `{path}`: {message}")]
+    Read { path: String, message: String },
+
+    #[error("Unable to parse TOML config file `{path}`: {message}")]
+    Parse { path: String, message: String },
+}
+
+/// Reads and parses the TOML config file.
+///
+/// # Errors
+///
+/// Returns an error if the file cannot be read, or is not a valid config.
+pub fn get_config_from_file<P: AsRef<Path>>(config_file_path: P) -> Result<Config, ConfigError> {
+    let path = config_file_path.as_ref().display().to_string();
+    let config_file_content =
+        fs::read_to_string(config_file_path).map_err(|error| ConfigError::Read {
+            path: path.clone(),
+            message: error.to_string(),
+        })?;
+    toml::from_str(&config_file_content).map_err(|error| ConfigError::Parse {
+        path,
+        message: error.to_string(),
+    })
 }
 
 pub fn get_custom_blocked_names<P: AsRef<Path>>(
@@ -46,51 +612,389 @@ pub fn get_custom_blocked_names<P: AsRef<Path>>(
         None
     };
     if let Some(value) = blocked_names_content {
-        parse_domainlist(&value, set);
+        let names: String = value
+            .lines()
+            .filter(|line| NamePattern::parse(line).is_none())
+            .fold(String::new(), |mut acc, line| {
+                acc.push_str(line);
+                acc.push('\n');
+                acc
+            });
+        parse_domainlist(&names, set, None);
+    }
+}
+
+/// Reads the regex and glob lines of the custom blocked names file.  Invalid patterns are logged
+/// and skipped.
+#[must_use]
+pub fn get_custom_blocked_patterns<P: AsRef<Path>>(blocked_names_path: P) -> Vec<NamePattern> {
+    let Ok(content) = fs::read_to_string(blocked_names_path) else {
+        return Vec::new();
     };
+    content
+        .lines()
+        .filter_map(NamePattern::parse)
+        .filter_map(|result| match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                warn!("{error}");
+                None
+            }
+        })
+        .collect()
+}
+
+/// Reads names which should never be blocked, even when listed by a source, one per line.
+#[must_use]
+pub fn get_custom_allowed_names<P: AsRef<Path>>(
+    allowed_names_path: P,
+) -> HashSet<Host, RandomState> {
+    let mut result = HashSet::<Host, RandomState>::default();
+    let allowed_names_display_path = allowed_names_path.as_ref().display().to_string();
+    if let Ok(value) = fs::read_to_string(allowed_names_path) {
+        parse_domainlist(&value, &mut result, None);
+    } else {
+        info!("No custom allowed names file found at `{allowed_names_display_path}`.");
+    }
+    result
+}
+
+/// Replaces the file at `path` with `content`, writing a temporary file alongside it first, then
+/// renaming it into place, so readers never see a partly written file.
+pub(crate) fn replace_file<P: AsRef<Path>>(path: P, content: &[u8]) -> io::Result<()> {
+    let path = path.as_ref();
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(".tmp");
+    let result =
+        fs::write(&temporary_path, content).and_then(|()| fs::rename(&temporary_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temporary_path);
+    }
+    result
 }
 
-#[derive(Template)]
-#[template(escape = "none", path = "blocklist.rpz")]
-struct BlocklistRPZTemplate<'a> {
-    domains: &'a str,
+fn write_to_file<P: AsRef<Path>>(content: &str, output_path: &P) -> io::Result<()> {
+    replace_file(output_path, content.as_bytes())?;
+    info!("Wrote data to file: {}", output_path.as_ref().display());
+    Ok(())
 }
 
-fn domain_to_blocklist_rpz_domain(host: &Host) -> String {
-    let domain = host.to_string();
-    format!("{domain}\tCNAME\t.\n*.{domain}\tCNAME\t.\n")
+/// Summary of changes made by writing a new output file, compared to the file it replaced.
+#[derive(Debug, PartialEq)]
+pub struct WriteSummary {
+    pub output_path: PathBuf,
+    pub record_count: usize,
+    pub added_count: usize,
+    pub removed_count: usize,
 }
 
-fn write_to_file<P: AsRef<Path>>(content: &str, output_path: &P) {
-    let output_display_path = output_path.as_ref().display().to_string();
-    let Ok(mut outfile) = File::create(output_path) else {
-        error!("Unable to create output file");
-        panic!("Error creating output file {output_display_path}")
+/// Domains listed in an existing output file.
+fn read_blocklist_domains<P: AsRef<Path>>(
+    format: OutputFormat,
+    output_path: P,
+) -> HashSet<String, RandomState> {
+    let Ok(content) = fs::read_to_string(output_path) else {
+        return HashSet::default();
     };
-    if outfile.write_all(content.as_bytes()).is_err() {
-        error!("Unable to write to output file {output_display_path}");
-        panic!("Error writing to output file");
-    }
-    info!("Wrote data to file: {output_display_path}");
-}
-
-pub fn write_blocklist_rpz_file(blocklist_domains: &[Host]) {
-    let domains = blocklist_domains
-        .iter()
-        .fold(String::new(), |mut acc, val| {
-            acc.push_str(&domain_to_blocklist_rpz_domain(val));
-            acc
-        });
-    let template = BlocklistRPZTemplate { domains: &domains };
-    let file_content = template
-        .render()
-        .expect("Unexpected error rendering template");
-    let output_path = PathBuf::from("./blocklist.rpz");
-    write_to_file(&file_content, &output_path);
+    content
+        .lines()
+        .filter_map(|line| format.parse_domain(line))
+        .map(String::from)
+        .collect()
+}
+
+fn write_summary(
+    output_path: PathBuf,
+    previous_domains: &HashSet<String, RandomState>,
+    blocklist_domains: &[Host],
+) -> WriteSummary {
+    let current_domains: HashSet<String, RandomState> =
+        blocklist_domains.iter().map(ToString::to_string).collect();
+    WriteSummary {
+        output_path,
+        record_count: current_domains.len(),
+        added_count: current_domains.difference(previous_domains).count(),
+        removed_count: previous_domains.difference(&current_domains).count(),
+    }
+}
+
+/// Writes the output file for `format`, leaving any existing file untouched when its content is
+/// unchanged.  Returns a summary of the changes, if the file was written.
+fn write_blocklist_file(
+    output: &Output,
+    format: OutputFormat,
+    blocklist: &Blocklist,
+) -> io::Result<Option<WriteSummary>> {
+    let file_content = format.render_blocklist(blocklist, output.source_comments);
+    let output_path = output.path(format);
+    if fs::read_to_string(&output_path).is_ok_and(|val| val == file_content) {
+        info!(
+            "No changes to blocklist, skipping write to {}",
+            output_path.display()
+        );
+        return Ok(None);
+    }
+    let previous_domains = read_blocklist_domains(format, &output_path);
+    write_to_file(&file_content, &output_path).map_err(|error| {
+        io::Error::new(
+            error.kind(),
+            format!(
+                "Unable to write output file `{}`: {error}",
+                output_path.display()
+            ),
+        )
+    })?;
     if let Ok(value) = fs::metadata(&output_path) {
         let bytes = value.len();
         let display_bytes = format_size(bytes, DECIMAL);
         let display_path = output_path.display();
         std::println!("Written {display_bytes} to {display_path}");
     }
+    let written_domains: &[Host] = if format.lists_domains() {
+        &blocklist.hosts
+    } else {
+        &[]
+    };
+    Ok(Some(write_summary(
+        output_path,
+        &previous_domains,
+        written_domains,
+    )))
+}
+
+/// Writes each configured output format, returning summaries for the files which changed.  A
+/// file which cannot be written is logged, and the remaining formats are still written.
+///
+/// # Errors
+///
+/// Returns the first error, if any output file or the Pi-hole gravity database cannot be written.
+pub fn write_blocklist_files(
+    output: &Output,
+    blocklist: &Blocklist,
+) -> io::Result<Vec<WriteSummary>> {
+    let mut result: Vec<WriteSummary> = Vec::new();
+    let mut first_error: Option<io::Error> = None;
+    for format in &output.formats {
+        match write_blocklist_file(output, *format, blocklist) {
+            Ok(value) => result.extend(value),
+            Err(error) => {
+                error!("{error}");
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+    if let Some(value) = &output.pihole_gravity_database {
+        match write_gravity_database_file(&output.directory.join(value), blocklist) {
+            Ok(value) => result.extend(value),
+            Err(error) => {
+                error!("{error}");
+                first_error.get_or_insert(error);
+            }
+        }
+    }
+    match first_error {
+        Some(error) => Err(error),
+        None => Ok(result),
+    }
+}
+
+/// Writes blocked domains to a Pi-hole gravity database, unless they are unchanged.  Returns a
+/// summary of the changes, if the domains changed.
+fn write_gravity_database_file(
+    path: &Path,
+    blocklist: &Blocklist,
+) -> io::Result<Option<WriteSummary>> {
+    let previous_domains = read_gravity_domains(path);
+    if path.exists()
+        && previous_domains.len() == blocklist.hosts.len()
+        && blocklist
+            .hosts
+            .iter()
+            .all(|val| previous_domains.contains(&val.to_string()))
+    {
+        info!("Pi-hole gravity database {} unchanged", path.display());
+        return Ok(None);
+    }
+    write_gravity_database(path, blocklist).map_err(|error| {
+        io::Error::other(format!(
+            "Unable to write Pi-hole gravity database `{}`: {error}",
+            path.display()
+        ))
+    })?;
+    std::println!(
+        "Written {} domains to {}",
+        blocklist.hosts.len(),
+        path.display()
+    );
+    let summary = write_summary(path.to_path_buf(), &previous_domains, &blocklist.hosts);
+    if summary.added_count == 0 && summary.removed_count == 0 {
+        return Ok(None);
+    }
+    Ok(Some(summary))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        read_blocklist_domains, replace_file, write_blocklist_files, write_gravity_database_file,
+        write_summary, Output, WriteSummary,
+    };
+    use crate::{output::OutputFormat, Blocklist};
+    use ahash::RandomState;
+    use rusqlite::Connection;
+    use std::{collections::HashSet, fs, path::PathBuf};
+    use url::Host;
+
+    #[test]
+    fn replace_file_swaps_in_new_content_without_leaving_temporary_file() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("blocklist.dnsmasq.conf");
+        fs::write(&path, "address=/old.example.com/#\n").unwrap();
+
+        // act
+        replace_file(&path, b"address=/new.example.com/#\n").unwrap();
+
+        // assert
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            "address=/new.example.com/#\n"
+        );
+        assert_eq!(fs::read_dir(directory.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn write_blocklist_files_writes_remaining_formats_after_failure() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let output = Output {
+            directory: directory.path().to_path_buf(),
+            formats: vec![OutputFormat::Rpz, OutputFormat::Hosts],
+            ..Output::default()
+        };
+        fs::create_dir(output.path(OutputFormat::Rpz)).unwrap();
+        let blocklist = Blocklist {
+            hosts: vec![Host::parse("ads.example.com").unwrap()],
+            ..Blocklist::default()
+        };
+
+        // act
+        let result = write_blocklist_files(&output, &blocklist);
+
+        // assert
+        assert!(result.is_err());
+        assert!(fs::read_to_string(output.path(OutputFormat::Hosts))
+            .unwrap()
+            .contains("ads.example.com"));
+    }
+
+    #[test]
+    fn write_gravity_database_file_returns_database_errors() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("gravity.db");
+        fs::write(&path, "not a database").unwrap();
+        let blocklist = Blocklist {
+            hosts: vec![Host::parse("ads.example.com").unwrap()],
+            ..Blocklist::default()
+        };
+
+        // act
+        let result = write_gravity_database_file(&path, &blocklist);
+
+        // assert
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn write_gravity_database_file_skips_unchanged_domains() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("gravity.db");
+        let blocklist = Blocklist {
+            hosts: vec![Host::parse("ads.example.com").unwrap()],
+            ..Blocklist::default()
+        };
+        let changed_blocklist = Blocklist {
+            hosts: vec![Host::parse("tracker.example.com").unwrap()],
+            ..Blocklist::default()
+        };
+        let updated = || -> String {
+            Connection::open(&path)
+                .unwrap()
+                .query_row(
+                    "SELECT value FROM info WHERE property = 'updated'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+
+        // act
+        let result_0 = write_gravity_database_file(&path, &blocklist);
+        Connection::open(&path)
+            .unwrap()
+            .execute("UPDATE info SET value = 'marker'", [])
+            .unwrap();
+        let result_1 = write_gravity_database_file(&path, &blocklist);
+        let updated_1 = updated();
+        let result_2 = write_gravity_database_file(&path, &changed_blocklist);
+
+        // assert
+        assert_eq!(result_0.unwrap().unwrap().added_count, 1);
+        assert!(result_1.unwrap().is_none());
+        assert_eq!(updated_1, "marker");
+        assert_eq!(result_2.unwrap().unwrap().removed_count, 1);
+        assert_ne!(updated(), "marker");
+    }
+
+    #[test]
+    fn read_blocklist_domains_skips_header_and_wildcard_records() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let rpz_path = directory.path().join("blocklist.rpz");
+        fs::write(
+            &rpz_path,
+            "$TTL\t60\n\tIN\tNS\tlocalhost.\n\nexample.com\tCNAME\t.\n*.example.com\tCNAME\t.\n",
+        )
+        .unwrap();
+
+        // act
+        let result = read_blocklist_domains(OutputFormat::Rpz, &rpz_path);
+
+        // assert
+        assert_eq!(result.len(), 1);
+        assert!(result.contains("example.com"));
+    }
+
+    #[test]
+    fn write_summary_counts_added_and_removed_domains() {
+        // arrange
+        let previous_domains: HashSet<String, RandomState> = ["example.com", "removed.example.com"]
+            .into_iter()
+            .map(String::from)
+            .collect();
+        let blocklist_domains = [
+            Host::parse("added.example.com").unwrap(),
+            Host::parse("example.com").unwrap(),
+        ];
+
+        // act
+        let result = write_summary(
+            PathBuf::from("blocklist.rpz"),
+            &previous_domains,
+            &blocklist_domains,
+        );
+
+        // assert
+        assert_eq!(
+            result,
+            WriteSummary {
+                output_path: PathBuf::from("blocklist.rpz"),
+                record_count: 2,
+                added_count: 1,
+                removed_count: 1,
+            }
+        );
+    }
 }
@@ -0,0 +1,272 @@
+use crate::Blocklist;
+use ahash::RandomState;
+use rusqlite::{params, Connection};
+use std::{
+    collections::HashMap,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use url::Host;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS hosts (
+    host TEXT PRIMARY KEY,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
+    removed INTEGER
+);
+CREATE TABLE IF NOT EXISTS host_sources (
+    host TEXT NOT NULL,
+    source TEXT NOT NULL,
+    first_seen INTEGER NOT NULL,
+    last_seen INTEGER NOT NULL,
+    PRIMARY KEY (host, source)
+);
+CREATE INDEX IF NOT EXISTS hosts_first_seen ON hosts (first_seen);
+CREATE INDEX IF NOT EXISTS hosts_removed ON hosts (removed);
+";
+
+/// Separates source names in the aggregated `sources` column of report queries
+const SOURCE_SEPARATOR: char = '\u{1f}';
+
+#[derive(thiserror::Error, Debug)]
+pub enum HistoryError {
+    #[error("History database error: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+/// A blocked host's history, as listed in reports.
+#[derive(Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub host: String,
+
+    /// When the host entered the blocklist, most recently if it was removed and returned since
+    pub first_seen: SystemTime,
+    pub last_seen: SystemTime,
+
+    /// When the host left the blocklist, or `None` while it is still listed
+    pub removed: Option<SystemTime>,
+
+    /// Sources listing the host when it was last seen
+    pub sources: Vec<String>,
+}
+
+fn to_timestamp(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map_or(0, |val| i64::try_from(val.as_secs()).unwrap_or(i64::MAX))
+}
+
+fn from_timestamp(timestamp: i64) -> SystemTime {
+    UNIX_EPOCH + Duration::from_secs(u64::try_from(timestamp).unwrap_or_default())
+}
+
+/// Database recording when each host entered and left the blocklist, and which sources
+/// listed it.
+pub struct HistoryStore {
+    connection: Connection,
+}
+
+impl HistoryStore {
+    /// Opens the database, creating it if missing.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be opened or created.
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, HistoryError> {
+        let connection = Connection::open(path)?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(HistoryStore { connection })
+    }
+
+    /// Records a build finishing at `now`: hosts in the blocklist are marked seen, and hosts
+    /// missing from it are marked removed.  Removed hosts which return are first seen again at
+    /// `now`.  Returns when each host in the blocklist was first seen.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be updated.
+    pub fn record(
+        &mut self,
+        blocklist: &Blocklist,
+        now: SystemTime,
+    ) -> Result<HashMap<Host, SystemTime, RandomState>, HistoryError> {
+        let now = to_timestamp(now);
+        let transaction = self.connection.transaction()?;
+        let mut result: HashMap<Host, SystemTime, RandomState> =
+            HashMap::with_capacity_and_hasher(blocklist.hosts.len(), RandomState::new());
+        {
+            let mut host_statement = transaction.prepare(
+                "INSERT INTO hosts (host, first_seen, last_seen) VALUES (?1, ?2, ?2)
+                 ON CONFLICT (host) DO UPDATE SET
+                     first_seen = CASE WHEN removed IS NULL THEN first_seen ELSE excluded.first_seen END,
+                     last_seen = excluded.last_seen,
+                     removed = NULL
+                 RETURNING first_seen",
+            )?;
+            let mut source_statement = transaction.prepare(
+                "INSERT INTO host_sources (host, source, first_seen, last_seen)
+                 VALUES (?1, ?2, ?3, ?3)
+                 ON CONFLICT (host, source) DO UPDATE SET last_seen = excluded.last_seen",
+            )?;
+            for host in &blocklist.hosts {
+                let name = host.to_string();
+                let first_seen: i64 =
+                    host_statement.query_row(params![name, now], |row| row.get(0))?;
+                result.insert(host.clone(), from_timestamp(first_seen));
+                for source in blocklist.host_sources(host) {
+                    source_statement.execute(params![name, source, now])?;
+                }
+            }
+        }
+        transaction.execute(
+            "UPDATE hosts SET removed = ?1 WHERE last_seen < ?1 AND removed IS NULL",
+            params![now],
+        )?;
+        transaction.commit()?;
+        Ok(result)
+    }
+
+    fn entries(
+        &self,
+        condition: &str,
+        since: SystemTime,
+    ) -> Result<Vec<HistoryEntry>, HistoryError> {
+        let mut statement = self.connection.prepare(&format!(
+            "SELECT hosts.host, hosts.first_seen, hosts.last_seen, hosts.removed,
+                 group_concat(host_sources.source, char(31))
+             FROM hosts
+             LEFT JOIN host_sources
+                 ON host_sources.host = hosts.host AND host_sources.last_seen = hosts.last_seen
+             WHERE {condition}
+             GROUP BY hosts.host
+             ORDER BY hosts.host"
+        ))?;
+        let rows = statement.query_map(params![to_timestamp(since)], |row| {
+            let sources: Option<String> = row.get(4)?;
+            Ok(HistoryEntry {
+                host: row.get(0)?,
+                first_seen: from_timestamp(row.get(1)?),
+                last_seen: from_timestamp(row.get(2)?),
+                removed: row.get::<_, Option<i64>>(3)?.map(from_timestamp),
+                sources: sources
+                    .map(|val| {
+                        let mut result: Vec<String> =
+                            val.split(SOURCE_SEPARATOR).map(String::from).collect();
+                        result.sort();
+                        result
+                    })
+                    .unwrap_or_default(),
+            })
+        })?;
+        Ok(rows.collect::<Result<Vec<HistoryEntry>, rusqlite::Error>>()?)
+    }
+
+    /// Hosts first seen at or after `since` which are still listed.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be read.
+    pub fn added_since(&self, since: SystemTime) -> Result<Vec<HistoryEntry>, HistoryError> {
+        self.entries("hosts.first_seen >= ?1 AND hosts.removed IS NULL", since)
+    }
+
+    /// Hosts which left the blocklist at or after `since`, and have not returned.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the database cannot be read.
+    pub fn removed_since(&self, since: SystemTime) -> Result<Vec<HistoryEntry>, HistoryError> {
+        self.entries("hosts.removed >= ?1", since)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HistoryStore;
+    use crate::Blocklist;
+    use std::time::{Duration, UNIX_EPOCH};
+    use url::Host;
+
+    fn blocklist(names: &[&str]) -> Blocklist {
+        let hosts: Vec<Host> = names.iter().map(|val| Host::parse(val).unwrap()).collect();
+        Blocklist {
+            provenance: hosts.iter().map(|val| (val.clone(), vec![0])).collect(),
+            hosts,
+            source_names: vec![String::from("yoyo")],
+            ..Blocklist::default()
+        }
+    }
+
+    #[test]
+    fn record_tracks_first_seen_and_removed_hosts() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let mut store = HistoryStore::open(directory.path().join("history.db")).unwrap();
+        let day_0 = UNIX_EPOCH + Duration::from_secs(86_400);
+        let day_1 = day_0 + Duration::from_secs(86_400);
+        store
+            .record(&blocklist(&["ads.example.com", "old.example.com"]), day_0)
+            .unwrap();
+
+        // act
+        let first_seen = store
+            .record(&blocklist(&["ads.example.com", "new.example.com"]), day_1)
+            .unwrap();
+        let added = store.added_since(day_1).unwrap();
+        let removed = store.removed_since(day_1).unwrap();
+
+        // assert
+        assert_eq!(
+            first_seen.get(&Host::parse("ads.example.com").unwrap()),
+            Some(&day_0)
+        );
+        assert_eq!(
+            added
+                .iter()
+                .map(|val| val.host.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["new.example.com"]
+        );
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].host, "old.example.com");
+        assert_eq!(removed[0].last_seen, day_0);
+        assert_eq!(removed[0].removed, Some(day_1));
+        assert_eq!(removed[0].sources, vec![String::from("yoyo")]);
+    }
+
+    #[test]
+    fn record_resets_first_seen_for_returning_hosts() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let mut store = HistoryStore::open(directory.path().join("history.db")).unwrap();
+        let day_0 = UNIX_EPOCH + Duration::from_secs(86_400);
+        let day_1 = day_0 + Duration::from_secs(86_400);
+        let day_2 = day_1 + Duration::from_secs(86_400);
+        store
+            .record(&blocklist(&["ads.example.com", "old.example.com"]), day_0)
+            .unwrap();
+        store
+            .record(&blocklist(&["ads.example.com"]), day_1)
+            .unwrap();
+
+        // act
+        let first_seen = store
+            .record(&blocklist(&["ads.example.com", "old.example.com"]), day_2)
+            .unwrap();
+        let added = store.added_since(day_2).unwrap();
+        let removed = store.removed_since(day_1).unwrap();
+
+        // assert
+        assert_eq!(
+            first_seen.get(&Host::parse("ads.example.com").unwrap()),
+            Some(&day_0)
+        );
+        assert_eq!(
+            first_seen.get(&Host::parse("old.example.com").unwrap()),
+            Some(&day_2)
+        );
+        assert_eq!(added.len(), 1);
+        assert_eq!(added[0].host, "old.example.com");
+        assert!(removed.is_empty());
+    }
+}
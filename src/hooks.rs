@@ -0,0 +1,184 @@
+use crate::file_system::WriteSummary;
+use log::{error, info};
+use std::env;
+use tokio::process::Command;
+
+#[derive(thiserror::Error, Debug)]
+pub enum HookError {
+    #[error("Unable to run post-write hook `{command}`: {message}")]
+    Spawn { command: String, message: String },
+
+    #[error("Post-write hook `{command}` failed with exit code {code}")]
+    Failed { command: String, code: i32 },
+
+    #[error("Post-write hook `{command}` was terminated by a signal")]
+    Terminated { command: String },
+}
+
+impl HookError {
+    /// Exit code for the process to return: the hook's own, if it exited unsuccessfully.
+    #[must_use]
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            HookError::Failed { code, .. } => *code,
+            HookError::Spawn { .. } | HookError::Terminated { .. } => 1,
+        }
+    }
+}
+
+fn shell_command(command: &str) -> Command {
+    if cfg!(windows) {
+        let mut result = Command::new("cmd");
+        result.arg("/C").arg(command);
+        result
+    } else {
+        let mut result = Command::new("sh");
+        result.arg("-c").arg(command);
+        result
+    }
+}
+
+async fn run_post_write_hook(command: &str, summaries: &[WriteSummary]) -> Result<(), HookError> {
+    let mut hook = shell_command(command);
+    if let Some(value) = summaries.first() {
+        hook.env("BLOCKLIST_OUTPUT_PATH", &value.output_path);
+    }
+    if let Ok(value) = env::join_paths(summaries.iter().map(|val| &val.output_path)) {
+        hook.env("BLOCKLIST_OUTPUT_PATHS", value);
+    }
+    // each file lists the same blocklist, so counts come from one file rather than adding up;
+    // the file listing the most records, as formats such as nftables list no domains
+    if let Some(value) = summaries.iter().max_by_key(|val| val.record_count) {
+        hook.env("BLOCKLIST_RECORD_COUNT", value.record_count.to_string())
+            .env("BLOCKLIST_ADDED_COUNT", value.added_count.to_string())
+            .env("BLOCKLIST_REMOVED_COUNT", value.removed_count.to_string());
+    }
+    info!("Running post-write hook `{command}`");
+    let output = hook.output().await.map_err(|error| HookError::Spawn {
+        command: command.into(),
+        message: error.to_string(),
+    })?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !stderr.trim().is_empty() {
+        error!("Post-write hook `{command}` output: {}", stderr.trim());
+    }
+    match output.status.code() {
+        Some(code) => Err(HookError::Failed {
+            command: command.into(),
+            code,
+        }),
+        None => Err(HookError::Terminated {
+            command: command.into(),
+        }),
+    }
+}
+
+/// Runs each hook in turn, once for all the output files changed by a build, reporting every
+/// failure.  Hooks see the first changed file in `BLOCKLIST_OUTPUT_PATH`, every changed file in
+/// `BLOCKLIST_OUTPUT_PATHS`, and the blocklist's record, added and removed counts once, rather
+/// than for each file.  Nothing runs if no file changed.  Returns the first failure.
+///
+/// # Errors
+///
+/// Returns an error if any hook cannot be run, or exits unsuccessfully.
+pub async fn run_post_write_hooks(
+    commands: &[String],
+    summaries: &[WriteSummary],
+) -> Result<(), HookError> {
+    if summaries.is_empty() {
+        return Ok(());
+    }
+    let mut first_error: Option<HookError> = None;
+    for command in commands {
+        if let Err(error) = run_post_write_hook(command, summaries).await {
+            error!("{error}");
+            first_error.get_or_insert(error);
+        }
+    }
+    match first_error {
+        Some(value) => Err(value),
+        None => Ok(()),
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{run_post_write_hooks, HookError};
+    use crate::file_system::WriteSummary;
+    use std::path::PathBuf;
+
+    fn summary() -> WriteSummary {
+        WriteSummary {
+            output_path: PathBuf::from("blocklist.rpz"),
+            record_count: 5,
+            added_count: 2,
+            removed_count: 1,
+        }
+    }
+
+    #[tokio::test]
+    async fn run_post_write_hooks_passes_summary_as_environment_variables() {
+        // arrange
+        let commands = vec![String::from(
+            r#"test "$BLOCKLIST_OUTPUT_PATH $BLOCKLIST_RECORD_COUNT $BLOCKLIST_ADDED_COUNT $BLOCKLIST_REMOVED_COUNT" = "blocklist.rpz 5 2 1""#,
+        )];
+
+        // act
+        let result = run_post_write_hooks(&commands, &[summary()]).await;
+
+        // assert
+        assert!(result.is_ok());
+    }
+
+    #[tokio::test]
+    async fn run_post_write_hooks_runs_once_for_all_changed_files() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let marker_path = directory.path().join("marker");
+        let commands = vec![format!(
+            r#"echo "$BLOCKLIST_OUTPUT_PATHS $BLOCKLIST_RECORD_COUNT" >> {}"#,
+            marker_path.display()
+        )];
+        let summaries = [
+            summary(),
+            WriteSummary {
+                output_path: PathBuf::from("blocklist.hosts"),
+                ..summary()
+            },
+        ];
+
+        // act
+        let result = run_post_write_hooks(&commands, &summaries).await;
+
+        // assert
+        assert!(result.is_ok());
+        assert_eq!(
+            std::fs::read_to_string(marker_path).unwrap(),
+            "blocklist.rpz:blocklist.hosts 5\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn run_post_write_hooks_returns_first_failure_after_running_all_hooks() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let marker_path = directory.path().join("marker");
+        let commands = vec![
+            String::from("exit 3"),
+            String::from("exit 4"),
+            format!("touch {}", marker_path.display()),
+        ];
+
+        // act
+        let result = run_post_write_hooks(&commands, &[summary()]).await;
+
+        // assert
+        let error = result.unwrap_err();
+        assert!(matches!(error, HookError::Failed { code: 3, .. }));
+        assert_eq!(error.exit_code(), 3);
+        assert!(marker_path.exists());
+    }
+}
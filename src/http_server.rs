@@ -0,0 +1,567 @@
+use crate::{
+    file_system::{Metrics, Output},
+    output::OutputFormat,
+};
+use bytes::Bytes;
+use flate2::{write::GzEncoder, Compression};
+use http_body_util::Full;
+use hyper::{
+    header::{self, HeaderValue},
+    server::conn::http1,
+    service::service_fn,
+    Method, Request, Response, StatusCode,
+};
+use hyper_util::rt::TokioIo;
+use log::{error, info, trace};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fmt::Write as _,
+    io::{self, Write},
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{io::AsyncReadExt, net::TcpListener};
+
+/// Output file content, kept until the file on disk changes.
+#[derive(Clone)]
+struct CachedOutput {
+    modified: SystemTime,
+    length: u64,
+    etag: String,
+    domain_count: usize,
+    body: Bytes,
+    gzip_body: Option<Bytes>,
+}
+
+impl CachedOutput {
+    fn gzip_etag(&self) -> String {
+        format!("{}-gzip\"", self.etag.trim_end_matches('"'))
+    }
+}
+
+pub struct HttpServer {
+    output: Output,
+
+    /// Build metrics written by `daemon`, included in `/metrics`
+    metrics_textfile: Option<PathBuf>,
+
+    /// Build metrics rendered by builds in this process, included in `/metrics` in place of the
+    /// textfile
+    build_metrics: Option<Arc<Mutex<String>>>,
+    cache: Mutex<HashMap<OutputFormat, CachedOutput>>,
+    request_count: AtomicU64,
+}
+
+fn seconds_since_epoch(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|val| val.as_secs())
+        .unwrap_or_default()
+}
+
+fn gzip(content: &[u8]) -> io::Result<Bytes> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(content)?;
+    Ok(Bytes::from(encoder.finish()?))
+}
+
+fn accepts_gzip<B>(request: &Request<B>) -> bool {
+    request
+        .headers()
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|val| val.to_str().ok())
+        .flat_map(|val| val.split(','))
+        .any(|val| {
+            let mut parts = val.trim().split(';');
+            parts.next() == Some("gzip") && parts.all(|param| param.trim() != "q=0")
+        })
+}
+
+/// Checks `If-None-Match`, falling back to `If-Modified-Since` when it is absent, as in RFC 9110
+/// section 13.2.2.
+fn is_not_modified<B>(request: &Request<B>, cached: &CachedOutput) -> bool {
+    let headers = request.headers();
+    if let Some(value) = headers
+        .get(header::IF_NONE_MATCH)
+        .and_then(|val| val.to_str().ok())
+    {
+        let gzip_etag = cached.gzip_etag();
+        return value.split(',').map(str::trim).any(|val| {
+            let val = val.trim_start_matches("W/");
+            val == "*" || val == cached.etag || val == gzip_etag
+        });
+    }
+    headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|val| val.to_str().ok())
+        .and_then(|val| httpdate::parse_http_date(val).ok())
+        .is_some_and(|val| seconds_since_epoch(cached.modified) <= seconds_since_epoch(val))
+}
+
+fn text_response(status: StatusCode, body: &'static str) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from_static(body.as_bytes())));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("text/plain; charset=utf-8"),
+    );
+    response
+}
+
+impl HttpServer {
+    #[must_use]
+    pub fn new(output: Output, metrics_textfile: Option<PathBuf>) -> Self {
+        HttpServer {
+            output,
+            metrics_textfile,
+            build_metrics: None,
+            cache: Mutex::new(HashMap::new()),
+            request_count: AtomicU64::new(0),
+        }
+    }
+
+    /// Serves `build_metrics`, kept up to date by builds in this process, on `/metrics`, rather
+    /// than reading the metrics textfile.
+    #[must_use]
+    pub fn with_build_metrics(mut self, build_metrics: Arc<Mutex<String>>) -> Self {
+        self.build_metrics = Some(build_metrics);
+        self
+    }
+
+    /// Returns the output file content, reading it from disk only when it has changed since it
+    /// was last cached.  Output files are replaced by renaming, so the metadata and content are
+    /// read through one handle, and always belong to the same version of the file.
+    async fn load(&self, format: OutputFormat) -> io::Result<CachedOutput> {
+        let path = self.output.path(format);
+        let mut file = tokio::fs::File::open(&path).await?;
+        let metadata = file.metadata().await?;
+        let modified = metadata.modified()?;
+        let length = metadata.len();
+        if let Some(value) = self.cache.lock().unwrap().get(&format) {
+            if value.modified == modified && value.length == length {
+                return Ok(value.clone());
+            }
+        }
+
+        let mut content = Vec::new();
+        file.read_to_end(&mut content).await?;
+        let modified_nanos = modified
+            .duration_since(UNIX_EPOCH)
+            .map(|val| val.as_nanos())
+            .unwrap_or_default();
+        let domain_count = String::from_utf8_lossy(&content)
+            .lines()
+            .filter(|val| format.parse_domain(val).is_some())
+            .count();
+        let cached = CachedOutput {
+            modified,
+            length,
+            etag: format!("\"{length:x}-{modified_nanos:x}\""),
+            domain_count,
+            body: Bytes::from(content),
+            gzip_body: None,
+        };
+        self.cache.lock().unwrap().insert(format, cached.clone());
+        Ok(cached)
+    }
+
+    async fn gzip_body(&self, format: OutputFormat, cached: &CachedOutput) -> io::Result<Bytes> {
+        if let Some(value) = &cached.gzip_body {
+            return Ok(value.clone());
+        }
+        let body = cached.body.clone();
+        let result = tokio::task::spawn_blocking(move || gzip(&body))
+            .await
+            .map_err(io::Error::other)??;
+        if let Some(value) = self.cache.lock().unwrap().get_mut(&format) {
+            if value.etag == cached.etag {
+                value.gzip_body = Some(result.clone());
+            }
+        }
+        Ok(result)
+    }
+
+    async fn output_response<B>(
+        &self,
+        request: &Request<B>,
+        format: OutputFormat,
+    ) -> Response<Full<Bytes>> {
+        let cached = match self.load(format).await {
+            Ok(value) => value,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => {
+                return text_response(StatusCode::NOT_FOUND, "Not found\n")
+            }
+            Err(error) => {
+                log::error!("Unable to read {}: {error}", format.file_name());
+                return text_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal error\n");
+            }
+        };
+
+        let gzip = accepts_gzip(request);
+        let etag = if gzip {
+            cached.gzip_etag()
+        } else {
+            cached.etag.clone()
+        };
+        let body = if is_not_modified(request, &cached) {
+            None
+        } else if gzip {
+            match self.gzip_body(format, &cached).await {
+                Ok(value) => Some(value),
+                Err(error) => {
+                    log::error!("Unable to compress {}: {error}", format.file_name());
+                    return text_response(StatusCode::INTERNAL_SERVER_ERROR, "Internal error\n");
+                }
+            }
+        } else {
+            Some(cached.body.clone())
+        };
+
+        let mut response = match body {
+            Some(value) if request.method() == Method::GET => Response::new(Full::new(value)),
+            Some(_) => Response::new(Full::default()),
+            None => {
+                let mut result = Response::new(Full::default());
+                *result.status_mut() = StatusCode::NOT_MODIFIED;
+                result
+            }
+        };
+        let headers = response.headers_mut();
+        if let Ok(value) = HeaderValue::from_str(&etag) {
+            headers.insert(header::ETAG, value);
+        }
+        if let Ok(value) = HeaderValue::from_str(&httpdate::fmt_http_date(cached.modified)) {
+            headers.insert(header::LAST_MODIFIED, value);
+        }
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(format.content_type()),
+        );
+        headers.insert(header::VARY, HeaderValue::from_static("Accept-Encoding"));
+        if gzip {
+            headers.insert(header::CONTENT_ENCODING, HeaderValue::from_static("gzip"));
+        }
+        response
+    }
+
+    async fn healthz_response(&self) -> Response<Full<Bytes>> {
+        for format in &self.output.formats {
+            if self.load(*format).await.is_err() {
+                return text_response(StatusCode::SERVICE_UNAVAILABLE, "unavailable\n");
+            }
+        }
+        text_response(StatusCode::OK, "ok\n")
+    }
+
+    async fn metrics_response(&self) -> Response<Full<Bytes>> {
+        let mut body = String::new();
+        let _ = writeln!(
+            body,
+            "# HELP blocklist_generator_http_requests_total HTTP requests received.\n\
+             # TYPE blocklist_generator_http_requests_total counter\n\
+             blocklist_generator_http_requests_total {}",
+            self.request_count.load(Ordering::Relaxed)
+        );
+        let mut outputs: Vec<(OutputFormat, CachedOutput)> = Vec::new();
+        for format in &self.output.formats {
+            if let Ok(value) = self.load(*format).await {
+                outputs.push((*format, value));
+            }
+        }
+        body.push_str(
+            "# HELP blocklist_generator_output_domains Blocked domains in the output file.\n\
+             # TYPE blocklist_generator_output_domains gauge\n",
+        );
+        for (format, cached) in &outputs {
+            let _ = writeln!(
+                body,
+                "blocklist_generator_output_domains{{format=\"{}\"}} {}",
+                format.name(),
+                cached.domain_count
+            );
+        }
+        body.push_str(
+            "# HELP blocklist_generator_output_bytes Size of the output file.\n\
+             # TYPE blocklist_generator_output_bytes gauge\n",
+        );
+        for (format, cached) in &outputs {
+            let _ = writeln!(
+                body,
+                "blocklist_generator_output_bytes{{format=\"{}\"}} {}",
+                format.name(),
+                cached.length
+            );
+        }
+        body.push_str(
+            "# HELP blocklist_generator_output_last_modified_timestamp_seconds Modification time of the output file.\n\
+             # TYPE blocklist_generator_output_last_modified_timestamp_seconds gauge\n",
+        );
+        for (format, cached) in &outputs {
+            let _ = writeln!(
+                body,
+                "blocklist_generator_output_last_modified_timestamp_seconds{{format=\"{}\"}} {}",
+                format.name(),
+                seconds_since_epoch(cached.modified)
+            );
+        }
+        if let Some(value) = &self.build_metrics {
+            body.push_str(&value.lock().unwrap());
+        } else if let Some(value) = &self.metrics_textfile {
+            match tokio::fs::read_to_string(value).await {
+                Ok(value) => body.push_str(&value),
+                Err(error) => trace!("Unable to read metrics `{}`: {error}", value.display()),
+            }
+        }
+
+        let mut response = Response::new(Full::new(Bytes::from(body)));
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; version=0.0.4; charset=utf-8"),
+        );
+        response
+    }
+
+    async fn handle<B>(&self, request: &Request<B>) -> Response<Full<Bytes>> {
+        self.request_count.fetch_add(1, Ordering::Relaxed);
+        if request.method() != Method::GET && request.method() != Method::HEAD {
+            return text_response(StatusCode::METHOD_NOT_ALLOWED, "Method not allowed\n");
+        }
+        match request.uri().path() {
+            "/healthz" => self.healthz_response().await,
+            "/metrics" => self.metrics_response().await,
+            path => {
+                let format = self
+                    .output
+                    .formats
+                    .iter()
+                    .find(|val| path.strip_prefix('/') == Some(val.file_name()));
+                match format {
+                    Some(value) => self.output_response(request, *value).await,
+                    None => text_response(StatusCode::NOT_FOUND, "Not found\n"),
+                }
+            }
+        }
+    }
+
+    /// Serves the configured output files, as `/blocklist.rpz` and so on, alongside `/healthz`
+    /// and `/metrics` endpoints.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if accepting a connection fails.
+    pub async fn run(self, listener: TcpListener) -> io::Result<()> {
+        if let Ok(value) = listener.local_addr() {
+            info!("Listening for HTTP requests on {value}");
+        }
+        let server = Arc::new(self);
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = Arc::clone(&server);
+            tokio::spawn(async move {
+                let service = service_fn(move |request| {
+                    let server = Arc::clone(&server);
+                    async move { Ok::<_, Infallible>(server.handle(&request).await) }
+                });
+                if let Err(error) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    trace!("Closed HTTP connection from {peer}: {error}");
+                }
+            });
+        }
+    }
+}
+
+/// Serves `/metrics` and `/healthz` on the address configured in the `[metrics]` section, if any,
+/// for long-running modes other than `serve-http`.  Metrics come from `build_metrics`, when this
+/// process builds the blocklist, or else from the metrics textfile.
+///
+/// # Errors
+///
+/// Returns an error if the address cannot be bound.
+pub async fn spawn_metrics_endpoint(
+    metrics: Option<&Metrics>,
+    build_metrics: Option<Arc<Mutex<String>>>,
+) -> io::Result<()> {
+    let Some(Metrics {
+        textfile,
+        listen: Some(listen),
+    }) = metrics
+    else {
+        return Ok(());
+    };
+    let listener = TcpListener::bind(listen).await?;
+    let output = Output {
+        formats: Vec::new(),
+        ..Output::default()
+    };
+    let mut server = HttpServer::new(output, textfile.clone());
+    if let Some(value) = build_metrics {
+        server = server.with_build_metrics(value);
+    }
+    tokio::spawn(async move {
+        if let Err(error) = server.run(listener).await {
+            error!("Metrics endpoint stopped: {error}");
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HttpServer;
+    use crate::{file_system::Output, output::OutputFormat};
+    use flate2::read::GzDecoder;
+    use http_body_util::BodyExt;
+    use hyper::{header, Request, StatusCode};
+    use std::{
+        fs,
+        io::Read,
+        sync::{Arc, Mutex},
+    };
+
+    const RPZ_CONTENT: &str = "$TTL\t60\n\nexample.com\tCNAME\t.\n*.example.com\tCNAME\t.\n";
+
+    fn server(directory: &tempfile::TempDir) -> HttpServer {
+        HttpServer::new(
+            Output {
+                directory: directory.path().to_path_buf(),
+                formats: vec![OutputFormat::Rpz],
+                source_comments: false,
+                pihole_gravity_database: None,
+            },
+            None,
+        )
+    }
+
+    #[tokio::test]
+    async fn handle_serves_output_with_validators_and_honours_conditional_requests() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        fs::write(directory.path().join("blocklist.rpz"), RPZ_CONTENT).unwrap();
+        let server = server(&directory);
+
+        // act
+        let response_0 = server
+            .handle(&Request::get("/blocklist.rpz").body(()).unwrap())
+            .await;
+        let etag = response_0.headers()[header::ETAG].clone();
+        let last_modified = response_0.headers()[header::LAST_MODIFIED].clone();
+        let response_1 = server
+            .handle(
+                &Request::get("/blocklist.rpz")
+                    .header(header::IF_NONE_MATCH, etag)
+                    .body(())
+                    .unwrap(),
+            )
+            .await;
+        let response_2 = server
+            .handle(
+                &Request::get("/blocklist.rpz")
+                    .header(header::IF_MODIFIED_SINCE, last_modified)
+                    .body(())
+                    .unwrap(),
+            )
+            .await;
+
+        // assert
+        assert_eq!(response_0.status(), StatusCode::OK);
+        let body = response_0.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body, RPZ_CONTENT.as_bytes());
+        assert_eq!(response_1.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response_2.status(), StatusCode::NOT_MODIFIED);
+    }
+
+    #[tokio::test]
+    async fn handle_compresses_output_when_client_accepts_gzip() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        fs::write(directory.path().join("blocklist.rpz"), RPZ_CONTENT).unwrap();
+        let server = server(&directory);
+        let request = Request::get("/blocklist.rpz")
+            .header(header::ACCEPT_ENCODING, "br, gzip")
+            .body(())
+            .unwrap();
+
+        // act
+        let response = server.handle(&request).await;
+
+        // assert
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        let body = response.into_body().collect().await.unwrap().to_bytes();
+        let mut decompressed = String::new();
+        GzDecoder::new(&body[..])
+            .read_to_string(&mut decompressed)
+            .unwrap();
+        assert_eq!(decompressed, RPZ_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn handle_reports_health_metrics_and_unknown_paths() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let server = server(&directory);
+
+        // act
+        let response_0 = server
+            .handle(&Request::get("/healthz").body(()).unwrap())
+            .await;
+        fs::write(directory.path().join("blocklist.rpz"), RPZ_CONTENT).unwrap();
+        let response_1 = server
+            .handle(&Request::get("/healthz").body(()).unwrap())
+            .await;
+        let response_2 = server
+            .handle(&Request::get("/metrics").body(()).unwrap())
+            .await;
+        let response_3 = server
+            .handle(&Request::get("/blocklist.hosts").body(()).unwrap())
+            .await;
+
+        // assert
+        assert_eq!(response_0.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response_1.status(), StatusCode::OK);
+        let metrics = response_2.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&metrics)
+            .contains("blocklist_generator_output_domains{format=\"rpz\"} 1\n"));
+        assert_eq!(response_3.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn handle_serves_build_metrics_in_place_of_textfile() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let textfile = directory.path().join("blocklist_generator.prom");
+        fs::write(&textfile, "blocklist_generator_domains 1\n").unwrap();
+        let build_metrics = Arc::new(Mutex::new(String::from("blocklist_generator_domains 2\n")));
+        let server = HttpServer::new(
+            Output {
+                formats: Vec::new(),
+                ..Output::default()
+            },
+            Some(textfile),
+        )
+        .with_build_metrics(Arc::clone(&build_metrics));
+
+        // act
+        let response_0 = server
+            .handle(&Request::get("/metrics").body(()).unwrap())
+            .await;
+        *build_metrics.lock().unwrap() = String::from("blocklist_generator_domains 3\n");
+        let response_1 = server
+            .handle(&Request::get("/metrics").body(()).unwrap())
+            .await;
+
+        // assert
+        let metrics_0 = response_0.into_body().collect().await.unwrap().to_bytes();
+        let metrics_1 = response_1.into_body().collect().await.unwrap().to_bytes();
+        assert!(String::from_utf8_lossy(&metrics_0).ends_with("\nblocklist_generator_domains 2\n"));
+        assert!(String::from_utf8_lossy(&metrics_1).ends_with("\nblocklist_generator_domains 3\n"));
+    }
+}
@@ -0,0 +1,724 @@
+#![warn(clippy::all, clippy::pedantic)]
+
+//! Generate blocklists, for use with DNS firewalls, by merging domain lists and hosts files
+//! published online.
+//!
+//! The `blocklist-generator` CLI is built on this library, which can also build blocklists
+//! in-process:
+//!
+//! ```no_run
+//! use blocklist_generator::{
+//!     fetch::{Client, Provenance},
+//!     output::OutputFormat,
+//!     Source, SourceType,
+//! };
+//! use url::Host;
+//!
+//! # async fn example() -> Result<(), blocklist_generator::fetch::AppError> {
+//! let sources = [Source {
+//!     name: String::from("firebog"),
+//!     url: "https://v.firebog.net/hosts/AdguardDNS.txt",
+//!     source_type: SourceType::DomainList,
+//!     category: None,
+//!     weight: 1,
+//! }];
+//! let mut provenance = Provenance::default();
+//! Client::default().domainlists(&sources, &mut provenance).await?;
+//!
+//! let mut domains: Vec<Host> = provenance.into_keys().collect();
+//! domains.sort();
+//! let rpz = OutputFormat::Rpz.render(&domains);
+//! # Ok(())
+//! # }
+//! ```
+
+/// Periodic rebuilds, for long-running deployments
+pub mod daemon;
+mod dns;
+/// DNS forwarder answering blocked names locally
+pub mod dns_server;
+/// Fetching and parsing blocklist sources
+pub mod fetch;
+/// Config file, custom names and output files
+pub mod file_system;
+/// When blocked hosts entered and left the blocklist
+pub mod history;
+/// Commands run after output files change
+pub mod hooks;
+/// HTTP server for output files
+pub mod http_server;
+/// Text and JSON log output
+pub mod logging;
+/// Prometheus metrics for each build
+pub mod metrics;
+/// Output file formats
+pub mod output;
+/// Parsers for domain lists and hosts files
+pub mod parse;
+/// Regex and glob rules from the custom blocked names file
+pub mod pattern;
+/// Pi-hole gravity database output
+pub mod pihole;
+/// Public Suffix List lookups
+pub mod public_suffix;
+/// Finding which sources block a name
+pub mod query;
+/// Resolving blocked domains to addresses, for firewall output
+pub mod resolve;
+/// Keeping hosts which drop out of sources for a grace period
+pub mod retention;
+/// Checks blocked names are valid DNS names
+pub mod validate;
+/// Versioned snapshots of the response policy zone
+pub mod zone;
+/// Zone transfer primary for the response policy zone
+pub mod zone_server;
+
+use ahash::RandomState;
+use fetch::{AppError, Client as FetchClient, Provenance};
+use file_system::{
+    get_custom_allowed_names, get_custom_blocked_names, get_custom_blocked_patterns, Blocklists,
+    Config, PublicSuffixAction, PublicSuffixes,
+};
+use history::HistoryStore;
+use ipnet::IpNet;
+use log::{info, warn};
+use metrics::BuildMetrics;
+use pattern::NamePattern;
+use public_suffix::PublicSuffixList;
+use resolve::Resolver;
+use retention::retain_recent_hosts;
+use serde::Deserialize;
+use std::{
+    collections::{HashMap, HashSet},
+    net::IpAddr,
+    path::Path,
+    time::SystemTime,
+};
+use url::{Host, Url};
+use validate::{validate, write_rejected_report, RejectReason, RejectedEntry, Strictness};
+
+/// Format of a blocklist source.
+#[derive(Clone, Copy, Debug, Deserialize, Eq, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceType {
+    /// One domain per line, `example.com` for example
+    DomainList,
+
+    /// Hosts file lines, `0.0.0.0 example.com` for example
+    HostsFile,
+
+    /// IP addresses and CIDR ranges, `198.51.100.0/24` for example, blocked when they appear in
+    /// DNS answers
+    IpList,
+
+    /// IP addresses and CIDR ranges of clients whose queries are blocked
+    ClientIpList,
+}
+
+/// A blocklist published online.
+#[derive(Debug)]
+pub struct Source<'a> {
+    /// Short name, used in provenance comments
+    pub name: String,
+    pub url: &'a str,
+    pub source_type: SourceType,
+
+    /// Category, selecting the minimum agreement needed, if any
+    pub category: Option<&'a str>,
+
+    /// Count towards the minimum agreement for each host listed
+    pub weight: u32,
+}
+
+/// Name for a source configured by URL alone: the URL host, with a numeric suffix for the second
+/// and later sources from the same host.
+fn default_source_name(url: &str, name_counts: &mut HashMap<String, usize>) -> String {
+    let host = Url::parse(url)
+        .ok()
+        .and_then(|val| val.host_str().map(String::from))
+        .unwrap_or_else(|| url.to_string());
+    let count = name_counts.entry(host.clone()).or_default();
+    *count += 1;
+    if *count == 1 {
+        host
+    } else {
+        format!("{host}-{count}")
+    }
+}
+
+/// Lists the sources configured in the `[blocklists]` section.
+#[must_use]
+pub fn sources_from_blocklists(blocklists: &Blocklists) -> Vec<Source<'_>> {
+    let mut result: Vec<Source> = Vec::new();
+    let Blocklists {
+        hosts_file_blocklist_urls,
+        domain_blocklist_urls,
+        sources,
+        ..
+    } = blocklists;
+    let mut name_counts: HashMap<String, usize> = HashMap::new();
+
+    for val in hosts_file_blocklist_urls {
+        result.push(Source {
+            name: default_source_name(val, &mut name_counts),
+            url: val,
+            source_type: SourceType::HostsFile,
+            category: None,
+            weight: 1,
+        });
+    }
+    for val in domain_blocklist_urls {
+        result.push(Source {
+            name: default_source_name(val, &mut name_counts),
+            url: val,
+            source_type: SourceType::DomainList,
+            category: None,
+            weight: 1,
+        });
+    }
+    for val in sources {
+        result.push(Source {
+            name: val.name.clone(),
+            url: &val.url,
+            source_type: val.source_type,
+            category: val.category.as_deref(),
+            weight: val.weight,
+        });
+    }
+
+    result
+}
+
+/// Custom names added to every blocklist
+pub const BLOCKED_NAMES_PATH: &str = "blocked-names.txt";
+
+/// Custom names removed from every blocklist, overriding sources and `blocked-names.txt`
+pub const ALLOWED_NAMES_PATH: &str = "allowed-names.txt";
+
+/// Source name recorded for custom names from `blocked-names.txt`
+const CUSTOM_SOURCE_NAME: &str = "custom";
+
+/// Source name recorded for hosts kept by the retention grace period after every source dropped
+/// them
+const RETAINED_SOURCE_NAME: &str = "retained";
+
+/// Whether the sources listing a host agree enough for it to be blocked.  The weights of all the
+/// listing sources are summed, and must reach the lowest minimum set for any of their categories.
+fn meets_min_sources(source_ids: &[usize], sources: &[Source], blocklists: &Blocklists) -> bool {
+    let listing_sources = || source_ids.iter().filter_map(|id| sources.get(*id));
+    let total_weight: u32 = listing_sources().map(|val| val.weight).sum();
+    let required = listing_sources()
+        .map(|val| {
+            val.category
+                .and_then(|category| blocklists.categories.get(category))
+                .map_or(blocklists.min_sources, |category| category.min_sources)
+        })
+        .min()
+        .unwrap_or(blocklists.min_sources);
+    total_weight >= required
+}
+
+/// Records that a host is listed by `source_id`, unless already recorded, keeping source IDs in
+/// ascending order.
+fn add_source_id(source_ids: &mut Vec<usize>, source_id: usize) {
+    if let Err(index) = source_ids.binary_search(&source_id) {
+        source_ids.insert(index, source_id);
+    }
+}
+
+/// Keeps hosts listed by enough sources to meet `min_sources`, along with hosts matching any of
+/// `patterns`, which are attributed to the custom names source too.  Returns the number of hosts
+/// dropped.
+fn keep_agreed_hosts(
+    provenance: &mut Provenance,
+    sources: &[Source],
+    blocklists: &Blocklists,
+    patterns: &[NamePattern],
+    custom_source_id: usize,
+) -> usize {
+    let merged_count = provenance.len();
+    provenance.retain(|host, source_ids| {
+        if let Host::Domain(name) = host {
+            if patterns.iter().any(|val| val.is_match(name)) {
+                add_source_id(source_ids, custom_source_id);
+                return true;
+            }
+        }
+        meets_min_sources(source_ids, sources, blocklists)
+    });
+    merged_count - provenance.len()
+}
+
+/// Removes hosts which are not valid DNS names, returning them sorted by name.
+fn reject_invalid_names(
+    provenance: &mut Provenance,
+    source_names: &[String],
+    strictness: Strictness,
+) -> Vec<RejectedEntry> {
+    let mut result: Vec<RejectedEntry> = Vec::new();
+    provenance.retain(|host, source_ids| match validate(host, strictness) {
+        Ok(()) => true,
+        Err(reason) => {
+            result.push(RejectedEntry {
+                name: host.to_string(),
+                reason,
+                sources: source_ids
+                    .iter()
+                    .filter_map(|id| source_names.get(*id).cloned())
+                    .collect(),
+            });
+            false
+        }
+    });
+    if !result.is_empty() {
+        let mut reason_counts: HashMap<RejectReason, usize> = HashMap::new();
+        for entry in &result {
+            *reason_counts.entry(entry.reason).or_default() += 1;
+        }
+        for (reason, count) in reason_counts {
+            warn!("Rejected {count} names: {reason}");
+        }
+    }
+    result.sort_unstable_by(|entry_0, entry_1| entry_0.name.cmp(&entry_1.name));
+    result
+}
+
+/// Merged blocklist, remembering which sources listed each host.
+#[derive(Default)]
+pub struct Blocklist {
+    /// Blocked hosts, sorted
+    pub hosts: Vec<Host>,
+
+    /// Source names, indexed by the source IDs in `provenance`
+    pub source_names: Vec<String>,
+
+    /// Source URLs, or the custom names file path, indexed like `source_names`
+    pub source_urls: Vec<String>,
+
+    /// Source categories, indexed like `source_names`
+    pub source_categories: Vec<Option<String>>,
+
+    /// IDs of the sources listing each blocked host, in ascending order
+    pub provenance: Provenance,
+
+    /// Regex and glob rules from `blocked-names.txt`, for output formats able to express them
+    pub patterns: Vec<NamePattern>,
+
+    /// Networks blocked when they appear in DNS answers, from IP list sources
+    pub response_ip_networks: Vec<IpNet>,
+
+    /// Networks of clients whose queries are blocked, from client IP list sources
+    pub client_ip_networks: Vec<IpNet>,
+
+    /// Addresses blocked domains resolved to, when a firewall resolver is configured
+    pub resolved_addresses: Vec<IpAddr>,
+
+    /// When each blocked host first appeared in the blocklist, where known
+    pub first_seen: HashMap<Host, SystemTime, RandomState>,
+}
+
+impl Blocklist {
+    /// Names of the sources listing `host`.
+    #[must_use]
+    pub fn host_sources(&self, host: &Host) -> Vec<&str> {
+        self.provenance
+            .get(host)
+            .map(|ids| {
+                ids.iter()
+                    .filter_map(|id| self.source_names.get(*id).map(String::as_str))
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    /// Distinct categories of the sources listing `host`, sorted.
+    #[must_use]
+    pub fn host_categories(&self, host: &Host) -> Vec<&str> {
+        let mut result: Vec<&str> = self
+            .provenance
+            .get(host)
+            .map(|ids| {
+                ids.iter()
+                    .filter_map(|id| self.source_categories.get(*id)?.as_deref())
+                    .collect()
+            })
+            .unwrap_or_default();
+        result.sort_unstable();
+        result.dedup();
+        result
+    }
+}
+
+/// Removes blocked names which are public suffixes, or only logs them, as configured.
+fn remove_public_suffixes(
+    provenance: &mut Provenance,
+    source_names: &[String],
+    public_suffixes: &PublicSuffixes,
+) {
+    let public_suffix_list = PublicSuffixList::load(public_suffixes.list.as_ref());
+    let action = public_suffixes.action;
+    provenance.retain(|host, source_ids| {
+        let Host::Domain(name) = host else {
+            return true;
+        };
+        if !public_suffix_list.is_public_suffix(name) {
+            return true;
+        }
+        let listed_by: Vec<&str> = source_ids
+            .iter()
+            .filter_map(|id| source_names.get(*id).map(String::as_str))
+            .collect();
+        let listed_by = listed_by.join(", ");
+        if action == PublicSuffixAction::Reject {
+            warn!("Removing public suffix `{name}`, listed by {listed_by}");
+            false
+        } else {
+            warn!("Blocking public suffix `{name}`, listed by {listed_by}");
+            true
+        }
+    });
+}
+
+/// Addresses blocked domains resolve to, when a resolver is configured for firewall output.
+async fn resolve_firewall_addresses(config: &Config, hosts: &[Host]) -> Vec<IpAddr> {
+    match config.firewall.resolver {
+        Some(value) if config.output.formats.iter().any(|val| !val.lists_domains()) => {
+            info!("Resolving {} blocked domains", hosts.len());
+            Resolver::new(value, config.firewall.timeout, config.firewall.concurrency)
+                .resolve_all(hosts)
+                .await
+                .into_iter()
+                .collect()
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// Records the build in the history database, returning when each blocked host was first seen.
+/// Failures are logged, giving no first seen times.
+fn record_history(path: &Path, blocklist: &Blocklist) -> HashMap<Host, SystemTime, RandomState> {
+    HistoryStore::open(path)
+        .and_then(|mut store| store.record(blocklist, SystemTime::now()))
+        .unwrap_or_else(|error| {
+            warn!("Unable to update history `{}`: {error}", path.display());
+            HashMap::default()
+        })
+}
+
+/// Fetches and merges every configured source, keeping hosts listed by enough sources to meet the
+/// configured `min_sources`.  Custom names from `blocked-names.txt` are added, along with merged
+/// hosts matching its regex and glob lines, regardless of agreement.  With a retention grace
+/// period configured, hosts recently dropped by their sources are kept, attributed to a
+/// `retained` source.  Names in `allowed-names.txt` are removed, as are names which are not valid
+/// DNS names, and public suffixes, such as `co.uk`, unless configured only to warn about them.
+///
+/// # Errors
+///
+/// Returns an error if any source cannot be fetched.
+pub async fn build_blocklist(config: &Config) -> Result<Blocklist, AppError> {
+    build_blocklist_with_metrics(config, &mut BuildMetrics::default()).await
+}
+
+/// Fetches every source, merging hosts into `provenance`.  Returns the merged response and client
+/// IP networks.
+async fn fetch_sources(
+    fetch_client: &FetchClient,
+    sources: &[Source<'_>],
+    provenance: &mut Provenance,
+) -> Result<(Vec<IpNet>, Vec<IpNet>), AppError> {
+    fetch_client.domainlists(sources, provenance).await?;
+    let response_ip_networks = fetch_client.iplists(sources, SourceType::IpList).await?;
+    let client_ip_networks = fetch_client
+        .iplists(sources, SourceType::ClientIpList)
+        .await?;
+    Ok((response_ip_networks, client_ip_networks))
+}
+
+/// Builds the blocklist as `build_blocklist` does, recording fetch figures in `metrics`, even if
+/// the build fails, and build figures when it succeeds.  A failed build is recorded as such,
+/// keeping the figures from the last successful one.
+///
+/// # Errors
+///
+/// Returns an error if any source cannot be fetched.
+pub async fn build_blocklist_with_metrics(
+    config: &Config,
+    metrics: &mut BuildMetrics,
+) -> Result<Blocklist, AppError> {
+    let result = build_recording_metrics(config, metrics).await;
+    if result.is_err() {
+        metrics.record_failure();
+    }
+    result
+}
+
+async fn build_recording_metrics(
+    config: &Config,
+    metrics: &mut BuildMetrics,
+) -> Result<Blocklist, AppError> {
+    let sources = sources_from_blocklists(&config.blocklists);
+
+    let fetch_client = FetchClient::new(&config.http, &config.blocklists)?;
+    let hasher = RandomState::new();
+    let mut provenance: Provenance = HashMap::with_capacity_and_hasher(524_288, hasher);
+    let fetched = fetch_sources(&fetch_client, &sources, &mut provenance).await;
+    metrics.record_fetches(&sources, &fetch_client.take_fetch_stats());
+    let (response_ip_networks, client_ip_networks) = fetched?;
+
+    let custom_source_id = sources.len();
+    let patterns = get_custom_blocked_patterns(BLOCKED_NAMES_PATH);
+    let dropped_count = keep_agreed_hosts(
+        &mut provenance,
+        &sources,
+        &config.blocklists,
+        &patterns,
+        custom_source_id,
+    );
+    if dropped_count > 0 {
+        info!("Dropped {dropped_count} hosts listed by too few sources");
+    }
+
+    let mut source_urls: Vec<String> = sources.iter().map(|val| val.url.to_string()).collect();
+    source_urls.push(String::from(BLOCKED_NAMES_PATH));
+    let mut source_categories: Vec<Option<String>> = sources
+        .iter()
+        .map(|val| val.category.map(String::from))
+        .collect();
+    source_categories.push(None);
+    let mut source_names: Vec<String> = sources.into_iter().map(|val| val.name).collect();
+    source_names.push(String::from(CUSTOM_SOURCE_NAME));
+    if let Some(value) = &config.retention {
+        let retained_source_id = source_names.len();
+        source_urls.push(value.state_file.display().to_string());
+        source_categories.push(None);
+        source_names.push(String::from(RETAINED_SOURCE_NAME));
+        // only fetched sources are tracked, so custom names never count as listed upstream
+        retain_recent_hosts(
+            &mut provenance,
+            &source_names[..custom_source_id],
+            retained_source_id,
+            value.grace_period,
+            &value.state_file,
+        );
+    }
+    let mut custom_names: HashSet<Host, RandomState> = HashSet::default();
+    get_custom_blocked_names(BLOCKED_NAMES_PATH, &mut custom_names);
+    for custom_name in custom_names {
+        add_source_id(provenance.entry(custom_name).or_default(), custom_source_id);
+    }
+    for allowed_name in get_custom_allowed_names(ALLOWED_NAMES_PATH) {
+        provenance.remove(&allowed_name);
+    }
+
+    let rejected =
+        reject_invalid_names(&mut provenance, &source_names, config.validation.strictness);
+    if let Some(value) = &config.validation.rejected_report {
+        if let Err(error) = write_rejected_report(value, &rejected) {
+            warn!(
+                "Unable to write rejected entries report `{}`: {error}",
+                value.display()
+            );
+        }
+    }
+
+    remove_public_suffixes(&mut provenance, &source_names, &config.public_suffixes);
+
+    let mut hosts: Vec<Host> = provenance.keys().cloned().collect();
+    hosts.sort();
+
+    let resolved_addresses = resolve_firewall_addresses(config, &hosts).await;
+
+    let mut blocklist = Blocklist {
+        hosts,
+        source_names,
+        source_urls,
+        source_categories,
+        provenance,
+        patterns,
+        response_ip_networks,
+        client_ip_networks,
+        resolved_addresses,
+        first_seen: HashMap::default(),
+    };
+    if let Some(value) = &config.history {
+        blocklist.first_seen = record_history(&value.database, &blocklist);
+    }
+    metrics.record_build(&blocklist, SystemTime::now());
+    Ok(blocklist)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        add_source_id, keep_agreed_hosts, meets_min_sources, reject_invalid_names,
+        sources_from_blocklists,
+    };
+    use crate::{
+        fetch::Provenance,
+        file_system::Blocklists,
+        pattern::NamePattern,
+        validate::{RejectReason, RejectedEntry, Strictness},
+    };
+    use url::Host;
+
+    #[test]
+    fn sources_from_blocklists_names_sources_after_url_host() {
+        // arrange
+        let blocklists: Blocklists = toml::from_str(
+            r#"
+            hosts_file_blocklist_urls = ["https://pgl.yoyo.org/adservers/serverlist.php"]
+            domain_blocklist_urls = [
+              "https://v.firebog.net/hosts/AdguardDNS.txt",
+              "https://v.firebog.net/hosts/Easyprivacy.txt",
+            ]
+
+            [[sources]]
+            name = "notrack"
+            url = "https://quidsup.net/notrack/blocklist.php?download=malwaredomains"
+            type = "domain_list"
+            "#,
+        )
+        .unwrap();
+
+        // act
+        let result = sources_from_blocklists(&blocklists);
+
+        // assert
+        let names: Vec<&str> = result.iter().map(|val| val.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "pgl.yoyo.org",
+                "v.firebog.net",
+                "v.firebog.net-2",
+                "notrack"
+            ]
+        );
+    }
+
+    #[test]
+    fn meets_min_sources_sums_weights_against_category_minimum() {
+        // arrange
+        let blocklists: Blocklists = toml::from_str(
+            r#"
+            min_sources = 2
+
+            [[sources]]
+            name = "aggressive"
+            url = "https://example.net/aggressive.txt"
+            type = "domain_list"
+
+            [[sources]]
+            name = "other"
+            url = "https://example.net/other.txt"
+            type = "domain_list"
+
+            [[sources]]
+            name = "trusted"
+            url = "https://example.net/trusted.txt"
+            type = "domain_list"
+            weight = 2
+
+            [[sources]]
+            name = "malware"
+            url = "https://example.net/malware.txt"
+            type = "domain_list"
+            category = "malware"
+
+            [categories.malware]
+            min_sources = 1
+            "#,
+        )
+        .unwrap();
+        let sources = sources_from_blocklists(&blocklists);
+
+        // act
+        let result_0 = meets_min_sources(&[0], &sources, &blocklists);
+        let result_1 = meets_min_sources(&[0, 1], &sources, &blocklists);
+        let result_2 = meets_min_sources(&[2], &sources, &blocklists);
+        let result_3 = meets_min_sources(&[3], &sources, &blocklists);
+
+        // assert
+        assert!(!result_0);
+        assert!(result_1);
+        assert!(result_2);
+        assert!(result_3);
+    }
+
+    #[test]
+    fn keep_agreed_hosts_attributes_pattern_matches_to_custom_source_once() {
+        // arrange
+        let blocklists: Blocklists = toml::from_str(
+            r#"
+            domain_blocklist_urls = ["https://example.net/0.txt", "https://example.net/1.txt"]
+            min_sources = 2
+            "#,
+        )
+        .unwrap();
+        let sources = sources_from_blocklists(&blocklists);
+        let patterns = vec![NamePattern::parse("ads.*").unwrap().unwrap()];
+        let custom_source_id = sources.len();
+        let mut provenance: Provenance = [
+            (Host::parse("ads.example.com").unwrap(), vec![0]),
+            (Host::parse("other.example.com").unwrap(), vec![1]),
+        ]
+        .into_iter()
+        .collect();
+
+        // act
+        let dropped_count = keep_agreed_hosts(
+            &mut provenance,
+            &sources,
+            &blocklists,
+            &patterns,
+            custom_source_id,
+        );
+        let source_ids = provenance
+            .get_mut(&Host::parse("ads.example.com").unwrap())
+            .unwrap();
+        add_source_id(source_ids, custom_source_id);
+
+        // assert
+        assert_eq!(dropped_count, 1);
+        assert_eq!(provenance.len(), 1);
+        assert_eq!(
+            provenance[&Host::parse("ads.example.com").unwrap()],
+            vec![0, custom_source_id]
+        );
+    }
+
+    #[test]
+    fn reject_invalid_names_reports_rejected_entries_with_sources() {
+        // arrange
+        let mut provenance: Provenance = [
+            (Host::parse("0.0.0.0").unwrap(), vec![0, 1]),
+            (Host::parse("ads.example.com").unwrap(), vec![0]),
+            (Host::parse("-ads.example.com").unwrap(), vec![1]),
+        ]
+        .into_iter()
+        .collect();
+        let source_names = [String::from("yoyo"), String::from("firebog")];
+
+        // act
+        let result = reject_invalid_names(&mut provenance, &source_names, Strictness::Standard);
+
+        // assert
+        assert_eq!(
+            result,
+            vec![
+                RejectedEntry {
+                    name: String::from("-ads.example.com"),
+                    reason: RejectReason::HyphenPosition,
+                    sources: vec![String::from("firebog")],
+                },
+                RejectedEntry {
+                    name: String::from("0.0.0.0"),
+                    reason: RejectReason::IpAddress,
+                    sources: vec![String::from("yoyo"), String::from("firebog")],
+                },
+            ]
+        );
+        assert_eq!(provenance.len(), 1);
+    }
+}
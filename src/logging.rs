@@ -0,0 +1,100 @@
+use log::{
+    kv::{self, Key, Value, VisitSource},
+    LevelFilter, Record,
+};
+use serde_json::{Map, Number};
+use std::{io::Write, time::SystemTime};
+
+/// Format of log lines written to standard error.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Free-text lines
+    #[default]
+    Text,
+
+    /// One JSON object per line, with structured fields such as `source_url`, `error_kind`,
+    /// `duration_ms` and `hosts_parsed` alongside the message
+    Json,
+}
+
+/// Collects a record's key-value pairs as JSON fields.
+struct JsonFields<'a>(&'a mut Map<String, serde_json::Value>);
+
+impl<'kvs> VisitSource<'kvs> for JsonFields<'_> {
+    fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+        let value = if let Some(number) = value.to_u64() {
+            serde_json::Value::from(number)
+        } else if let Some(number) = value.to_i64() {
+            serde_json::Value::from(number)
+        } else if let Some(number) = value.to_f64().and_then(Number::from_f64) {
+            serde_json::Value::Number(number)
+        } else if let Some(boolean) = value.to_bool() {
+            serde_json::Value::Bool(boolean)
+        } else {
+            serde_json::Value::String(value.to_string())
+        };
+        self.0.insert(key.to_string(), value);
+        Ok(())
+    }
+}
+
+/// Log line for `record` as a JSON object, with the time, level, target and message, and any
+/// key-value pairs logged with it.
+fn json_line(record: &Record, time: SystemTime) -> String {
+    let mut fields = Map::new();
+    fields.insert(
+        String::from("timestamp"),
+        humantime::format_rfc3339_millis(time).to_string().into(),
+    );
+    fields.insert(String::from("level"), record.level().as_str().into());
+    fields.insert(String::from("target"), record.target().into());
+    fields.insert(String::from("message"), record.args().to_string().into());
+    // fields which fail to convert are left out, rather than losing the line
+    let _ = record.key_values().visit(&mut JsonFields(&mut fields));
+    serde_json::Value::Object(fields).to_string()
+}
+
+/// Sets up logging to standard error at `level`, in the given format.
+///
+/// # Panics
+///
+/// Panics if a logger is already set up.
+pub fn init(level: LevelFilter, format: LogFormat) {
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(level);
+    if format == LogFormat::Json {
+        builder.format(|buf, record| writeln!(buf, "{}", json_line(record, SystemTime::now())));
+    }
+    builder.init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::json_line;
+    use log::{Level, Record};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn json_line_includes_structured_fields() {
+        // arrange
+        let fields: &[(&str, &str)] = &[
+            ("source_url", "https://example.com/hosts"),
+            ("error_kind", "fetch_request"),
+        ];
+        let record = Record::builder()
+            .args(format_args!("Unable to fetch source"))
+            .level(Level::Error)
+            .target("blocklist_generator::fetch")
+            .key_values(&fields)
+            .build();
+
+        // act
+        let result = json_line(&record, UNIX_EPOCH + Duration::from_millis(1500));
+
+        // assert
+        assert_eq!(
+            result,
+            r#"{"error_kind":"fetch_request","level":"ERROR","message":"Unable to fetch source","source_url":"https://example.com/hosts","target":"blocklist_generator::fetch","timestamp":"1970-01-01T00:00:01.500Z"}"#
+        );
+    }
+}
@@ -1,19 +1,30 @@
 #![warn(clippy::all, clippy::pedantic)]
 
-mod fetch;
-mod file_system;
-mod parse;
-
-use ahash::RandomState;
-use clap::Parser;
-use fetch::Client as FetchClient;
-use file_system::{
-    get_blocklists_from_config_file, get_custom_blocked_names, write_blocklist_rpz_file, Blocklists,
+use blocklist_generator::{
+    build_blocklist_with_metrics, daemon,
+    dns_server::{Server as DnsServer, ServerConfig as DnsServerConfig},
+    file_system::{
+        get_config_from_file, write_blocklist_files, Config, History, HttpOverrides, Serve,
+        ZoneTransfer,
+    },
+    history::HistoryStore,
+    hooks::run_post_write_hooks,
+    http_server::{spawn_metrics_endpoint, HttpServer},
+    logging::{self, LogFormat},
+    metrics::BuildMetrics,
+    public_suffix::PublicSuffixList,
+    query::{query, MatchScope, RuleStatus},
+    zone::SnapshotStore,
+    zone_server::{publish_zone, ZoneServer},
 };
-use log::warn;
+use clap::{Parser, Subcommand};
 use num_format::{Locale, ToFormattedString};
-use std::{collections::HashSet, path::PathBuf};
-use url::Host;
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+use tokio::net::{TcpListener, UdpSocket};
 
 #[derive(Parser)]
 #[clap(author,version,about,long_about=None)]
@@ -21,52 +32,206 @@ struct Cli {
     #[clap(flatten)]
     verbose: clap_verbosity_flag::Verbosity,
 
+    #[clap(flatten)]
+    http: HttpOverrides,
+
+    /// Log line format
+    #[clap(long, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+
     /// Config file path (default: ./blocklist-generator.toml)
     #[clap(short, long, value_parser)]
     config: Option<PathBuf>,
+
+    #[clap(subcommand)]
+    command: Option<Command>,
 }
 
-#[derive(Debug)]
-enum SourceType {
-    DomainList,
-    HostsFile,
+#[derive(Subcommand)]
+enum Command {
+    /// Run a DNS forwarder, answering queries for blocked names locally and forwarding all others
+    /// to the upstream resolver configured in the `[serve]` section
+    Serve,
+
+    /// Keep running, rebuilding the blocklist on the interval configured in the `[daemon]`
+    /// section.  Send `SIGHUP` to reload the config file
+    Daemon,
+
+    /// Serve the output files over HTTP, with `/healthz` and `/metrics` endpoints.  Run alongside
+    /// `daemon` to keep the files up to date
+    ServeHttp,
+
+    /// Act as primary for the zone configured in the `[zone_transfer]` section, answering `AXFR`
+    /// and `IXFR` queries from secondaries.  Run alongside `daemon` to publish new serials
+    ServeZone,
+
+    /// Explain why a name is blocked, listing each source line matching the name or one of its
+    /// parent domains, and whether the build keeps it.  Sources are cached in the directory
+    /// configured in the `[query]` section
+    Query {
+        /// Name to look up, `ads.example.com` for example
+        name: String,
+    },
+
+    /// List names added to and removed from the blocklist recently, from the history database
+    /// configured in the `[history]` section
+    Report {
+        /// How far back to report, `7d` for example
+        #[clap(long, default_value = "7d")]
+        since: humantime::Duration,
+    },
 }
 
-#[derive(Debug)]
-struct Source<'a> {
-    url: &'a str,
-    source_type: SourceType,
+async fn serve(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(Serve {
+        listen,
+        upstream,
+        sinkhole_ipv4,
+        sinkhole_ipv6,
+        ttl,
+    }) = config.serve
+    else {
+        return Err("Missing `[serve]` section, with an `upstream` resolver, in config".into());
+    };
+
+    let mut metrics = BuildMetrics::default();
+    let result = build_blocklist_with_metrics(config, &mut metrics).await;
+    metrics.write_configured_textfile(config);
+    let result = result?;
+    spawn_metrics_endpoint(
+        config.metrics.as_ref(),
+        Some(Arc::new(Mutex::new(metrics.render()))),
+    )
+    .await?;
+    println!(
+        "Serving {} blocked names",
+        result.hosts.len().to_formatted_string(&Locale::en)
+    );
+
+    let server = DnsServer::new(
+        DnsServerConfig {
+            upstream,
+            sinkhole_ipv4,
+            sinkhole_ipv6,
+            ttl,
+        },
+        &result.hosts,
+    );
+    let udp_socket = UdpSocket::bind(listen).await?;
+    let tcp_listener = TcpListener::bind(listen).await?;
+    server.run(udp_socket, tcp_listener).await?;
+
+    Ok(())
 }
 
-fn sources_from_blocklists(blocklists: &Blocklists) -> Vec<Source> {
-    let mut result: Vec<Source> = Vec::new();
-    let Blocklists {
-        hosts_file_blocklist_urls,
-        domain_blocklist_urls,
-    } = blocklists;
-
-    for val in hosts_file_blocklist_urls {
-        result.push(Source {
-            url: val,
-            source_type: SourceType::HostsFile,
-        });
+async fn explain(config: &Config, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let matches = query(config, name).await?;
+    if matches.is_empty() {
+        println!("`{name}` is not blocked by any source");
+        return Ok(());
+    }
+
+    for rule_match in &matches {
+        let scope = match rule_match.scope {
+            MatchScope::Exact => "exact match",
+            MatchScope::ParentDomain => "parent domain",
+            MatchScope::Pattern => "pattern",
+        };
+        let status = match rule_match.status {
+            RuleStatus::Blocked => String::new(),
+            RuleStatus::TooFewSources => String::from(", listed by too few sources"),
+            RuleStatus::Allowed => String::from(", overridden by allowlist"),
+            RuleStatus::Invalid(reason) => format!(", rejected: {reason}"),
+            RuleStatus::PublicSuffix => String::from(", removed as a public suffix"),
+        };
+        println!(
+            "{} line {}: `{}` ({scope}{status})",
+            rule_match.source, rule_match.line_number, rule_match.rule
+        );
     }
-    for val in domain_blocklist_urls {
-        result.push(Source {
-            url: val,
-            source_type: SourceType::DomainList,
-        });
+    if matches.iter().any(|val| val.status == RuleStatus::Blocked) {
+        println!("`{name}` is blocked");
+    } else {
+        println!("`{name}` is not blocked: every matching rule is dropped from the blocklist");
     }
+    Ok(())
+}
+
+fn report(config: &Config, since: Duration) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(History { database }) = &config.history else {
+        return Err("Missing `[history]` section, with the history `database`, in config".into());
+    };
 
-    result
+    let store = HistoryStore::open(database)?;
+    let since = SystemTime::now().checked_sub(since).ok_or_else(|| {
+        format!(
+            "`--since` of {} reaches too far back",
+            humantime::format_duration(since)
+        )
+    })?;
+    let added = store.added_since(since)?;
+    println!(
+        "{} names added",
+        added.len().to_formatted_string(&Locale::en)
+    );
+    for entry in &added {
+        println!(
+            "+ {} (first seen {}, listed by {})",
+            entry.host,
+            humantime::format_rfc3339_seconds(entry.first_seen),
+            entry.sources.join(", ")
+        );
+    }
+    let removed = store.removed_since(since)?;
+    println!(
+        "{} names removed",
+        removed.len().to_formatted_string(&Locale::en)
+    );
+    for entry in &removed {
+        println!(
+            "- {} (first seen {}, last seen {}, listed by {})",
+            entry.host,
+            humantime::format_rfc3339_seconds(entry.first_seen),
+            humantime::format_rfc3339_seconds(entry.last_seen),
+            entry.sources.join(", ")
+        );
+    }
+    Ok(())
+}
+
+async fn serve_zone(config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let Some(zone_transfer) = &config.zone_transfer else {
+        return Err("Missing `[zone_transfer]` section, with the zone `origin`, in config".into());
+    };
+    let ZoneTransfer {
+        listen,
+        origin,
+        ttl,
+        snapshot_directory,
+        keep_snapshots,
+        notify,
+        ..
+    } = zone_transfer;
+
+    let server = ZoneServer::new(
+        origin,
+        *ttl,
+        SnapshotStore::new(snapshot_directory, *keep_snapshots),
+        zone_transfer.transfer_networks(),
+        notify.clone(),
+    );
+    spawn_metrics_endpoint(config.metrics.as_ref(), None).await?;
+    let udp_socket = UdpSocket::bind(listen).await?;
+    let tcp_listener = TcpListener::bind(listen).await?;
+    server.run(udp_socket, tcp_listener).await?;
+
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let cli = &Cli::parse();
-    env_logger::Builder::new()
-        .filter_level(cli.verbose.log_level_filter())
-        .init();
+    logging::init(cli.verbose.log_level_filter(), cli.log_format);
 
     let default_config_path = PathBuf::from("blocklist-generator.toml");
     let config_path = match &cli.config {
@@ -74,25 +239,54 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         None => &default_config_path,
     };
 
-    let blocklists = get_blocklists_from_config_file(config_path);
-    let sources = sources_from_blocklists(&blocklists);
-
-    let fetch_client = FetchClient::default();
-    let hasher = RandomState::new();
-    let mut set: HashSet<Host, RandomState> = HashSet::with_capacity_and_hasher(524_288, hasher);
-    fetch_client.domainlists(&sources, &mut set).await?;
+    let mut config = get_config_from_file(config_path)?;
+    cli.http.apply(&mut config.http);
 
-    set.remove(&Host::parse("0.0.0.0").unwrap());
-    set.remove(&Host::parse("127.0.0.1").unwrap()); // DevSkim: ignore DS162092 - use of localhost IP is for removal from generated file, and not for accessing the localhost
-    set.remove(&Host::parse("255.255.255.255").unwrap());
+    match &cli.command {
+        Some(Command::Serve) => return serve(&config).await,
+        Some(Command::Daemon) => return daemon::run(config_path, config, &cli.http).await,
+        Some(Command::ServeHttp) => {
+            let listener = TcpListener::bind(config.serve_http.listen).await?;
+            let metrics_textfile = config.metrics.and_then(|val| val.textfile);
+            HttpServer::new(config.output, metrics_textfile)
+                .run(listener)
+                .await?;
+            return Ok(());
+        }
+        Some(Command::ServeZone) => return serve_zone(&config).await,
+        Some(Command::Query { name }) => return explain(&config, name).await,
+        Some(Command::Report { since }) => return report(&config, **since),
+        None => {}
+    }
 
-    get_custom_blocked_names("blocked-names.txt", &mut set);
+    let mut metrics = BuildMetrics::default();
+    let result = build_blocklist_with_metrics(&config, &mut metrics).await;
+    metrics.write_configured_textfile(&config);
+    let result = result?;
 
-    let mut result: Vec<Host> = set.into_iter().collect();
-    result.sort();
+    let summaries = write_blocklist_files(&config.output, &result)?;
+    if let Some(value) = &config.zone_transfer {
+        publish_zone(value, &result)?;
+    }
 
-    write_blocklist_rpz_file(&result);
+    println!(
+        "{} results",
+        result.hosts.len().to_formatted_string(&Locale::en)
+    );
+    if config.public_suffixes.group_stats {
+        let public_suffix_list = PublicSuffixList::load(config.public_suffixes.list.as_ref());
+        for (domain, count) in public_suffix_list
+            .count_by_registrable_domain(&result.hosts)
+            .iter()
+            .take(10)
+        {
+            println!("{count:>9} {domain}");
+        }
+    }
 
-    println!("{} results", result.len().to_formatted_string(&Locale::en));
+    if let Err(error) = run_post_write_hooks(&config.hooks.post_write, &summaries).await {
+        // already logged; exit with the failing hook's own code
+        std::process::exit(error.exit_code());
+    }
     Ok(())
 }
@@ -0,0 +1,336 @@
+use crate::{fetch::FetchStats, file_system::Config, Blocklist, Source};
+use log::error;
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    fs, io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Fetch and build figures for one source.
+#[derive(Debug, Default, PartialEq)]
+struct SourceMetrics {
+    name: String,
+    url: String,
+    fetch_duration: Option<Duration>,
+    bytes: usize,
+
+    /// Hosts the source contributed to the last successful build
+    domains: Option<usize>,
+}
+
+/// Metrics for the latest build, and fetch failures since the process started, rendered in the
+/// Prometheus text exposition format.
+#[derive(Debug, Default)]
+pub struct BuildMetrics {
+    sources: Vec<SourceMetrics>,
+    fetch_failures: BTreeMap<&'static str, u64>,
+    domains: Option<usize>,
+    last_success: Option<SystemTime>,
+
+    /// Whether the latest build failed, leaving the figures from the last successful build
+    last_build_failed: bool,
+}
+
+/// Escapes a label value, as the text exposition format requires.
+fn label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn header(body: &mut String, name: &str, metric_type: &str, help: &str) {
+    let _ = writeln!(body, "# HELP {name} {help}\n# TYPE {name} {metric_type}");
+}
+
+impl BuildMetrics {
+    /// Records the outcome of fetching `sources`, replacing fetch figures from any earlier build,
+    /// and counts failed fetches by `AppError` kind.  Domain counts from the last successful build
+    /// are kept until the next one succeeds.
+    pub fn record_fetches(&mut self, sources: &[Source<'_>], stats: &[FetchStats]) {
+        self.sources = sources
+            .iter()
+            .map(|source| {
+                let fetch = stats.iter().find(|val| val.url == source.url);
+                let domains = self
+                    .sources
+                    .iter()
+                    .find(|val| val.url == source.url)
+                    .and_then(|val| val.domains);
+                SourceMetrics {
+                    name: source.name.clone(),
+                    url: source.url.to_string(),
+                    fetch_duration: fetch.map(|val| val.duration),
+                    bytes: fetch.map_or(0, |val| val.bytes),
+                    domains,
+                }
+            })
+            .collect();
+        for kind in stats.iter().filter_map(|val| val.error_kind) {
+            *self.fetch_failures.entry(kind).or_default() += 1;
+        }
+    }
+
+    /// Records a successful build, finishing at `now`.
+    pub fn record_build(&mut self, blocklist: &Blocklist, now: SystemTime) {
+        let mut source_counts = vec![0; self.sources.len()];
+        for source_ids in blocklist.provenance.values() {
+            for id in source_ids {
+                if let Some(value) = source_counts.get_mut(*id) {
+                    *value += 1;
+                }
+            }
+        }
+        for (source, count) in self.sources.iter_mut().zip(source_counts) {
+            source.domains = Some(count);
+        }
+        self.domains = Some(blocklist.hosts.len());
+        self.last_success = Some(now);
+        self.last_build_failed = false;
+    }
+
+    /// Records a failed build, keeping the figures from the last successful one.
+    pub fn record_failure(&mut self) {
+        self.last_build_failed = true;
+    }
+
+    /// Metrics in the Prometheus text exposition format.
+    #[must_use]
+    pub fn render(&self) -> String {
+        let mut body = String::new();
+        if let Some(value) = self.domains {
+            header(
+                &mut body,
+                "blocklist_generator_domains",
+                "gauge",
+                "Blocked domains in the last successful build.",
+            );
+            let _ = writeln!(body, "blocklist_generator_domains {value}");
+        }
+
+        header(
+            &mut body,
+            "blocklist_generator_source_domains",
+            "gauge",
+            "Blocked domains each source contributed to the last successful build.",
+        );
+        for source in &self.sources {
+            if let Some(value) = source.domains {
+                let _ = writeln!(
+                    body,
+                    "blocklist_generator_source_domains{{source=\"{}\",url=\"{}\"}} {value}",
+                    label_value(&source.name),
+                    label_value(&source.url)
+                );
+            }
+        }
+        header(
+            &mut body,
+            "blocklist_generator_source_fetch_duration_seconds",
+            "gauge",
+            "Time taken fetching each source on the last build.",
+        );
+        for source in &self.sources {
+            if let Some(value) = source.fetch_duration {
+                let _ = writeln!(
+                    body,
+                    "blocklist_generator_source_fetch_duration_seconds{{source=\"{}\"}} {}",
+                    label_value(&source.name),
+                    value.as_secs_f64()
+                );
+            }
+        }
+        header(
+            &mut body,
+            "blocklist_generator_source_bytes",
+            "gauge",
+            "Bytes downloaded from each source on the last build.",
+        );
+        for source in &self.sources {
+            let _ = writeln!(
+                body,
+                "blocklist_generator_source_bytes{{source=\"{}\"}} {}",
+                label_value(&source.name),
+                source.bytes
+            );
+        }
+        header(
+            &mut body,
+            "blocklist_generator_fetch_failures_total",
+            "counter",
+            "Failed source fetches, by error kind.",
+        );
+        for (kind, count) in &self.fetch_failures {
+            let _ = writeln!(
+                body,
+                "blocklist_generator_fetch_failures_total{{error_kind=\"{kind}\"}} {count}"
+            );
+        }
+
+        header(
+            &mut body,
+            "blocklist_generator_last_build_failed",
+            "gauge",
+            "Whether the latest build failed, so other figures are from an earlier build.",
+        );
+        let _ = writeln!(
+            body,
+            "blocklist_generator_last_build_failed {}",
+            u8::from(self.last_build_failed)
+        );
+
+        if let Some(value) = self.last_success {
+            header(
+                &mut body,
+                "blocklist_generator_last_success_timestamp_seconds",
+                "gauge",
+                "Time the last successful build finished.",
+            );
+            let _ = writeln!(
+                body,
+                "blocklist_generator_last_success_timestamp_seconds {}",
+                value
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |val| val.as_secs())
+            );
+        }
+        body
+    }
+
+    /// Writes the metrics for the node exporter textfile collector.  The file is replaced
+    /// atomically, so the collector never reads a partial file.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn write_textfile<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let temporary_path = path.with_extension("prom.tmp");
+        fs::write(&temporary_path, self.render())?;
+        fs::rename(temporary_path, path)
+    }
+
+    /// Writes the textfile configured in the `[metrics]` section, if any, logging failures.
+    pub fn write_configured_textfile(&self, config: &Config) {
+        if let Some(value) = config
+            .metrics
+            .as_ref()
+            .and_then(|val| val.textfile.as_ref())
+        {
+            if let Err(error) = self.write_textfile(value) {
+                error!("Unable to write metrics to `{}`: {error}", value.display());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BuildMetrics;
+    use crate::{fetch::FetchStats, Blocklist, Source, SourceType};
+    use std::time::{Duration, UNIX_EPOCH};
+    use url::Host;
+
+    #[test]
+    fn render_reports_source_figures_and_failures() {
+        // arrange
+        let sources = [
+            Source {
+                name: String::from("yoyo"),
+                url: "https://pgl.yoyo.org/adservers/serverlist.php",
+                source_type: SourceType::DomainList,
+                category: None,
+                weight: 1,
+            },
+            Source {
+                name: String::from("firebog"),
+                url: "https://v.firebog.net/hosts/AdguardDNS.txt",
+                source_type: SourceType::DomainList,
+                category: None,
+                weight: 1,
+            },
+        ];
+        let stats = [
+            FetchStats {
+                url: String::from(sources[0].url),
+                duration: Duration::from_millis(1500),
+                bytes: 2048,
+                error_kind: None,
+            },
+            FetchStats {
+                url: String::from(sources[1].url),
+                duration: Duration::from_millis(100),
+                bytes: 0,
+                error_kind: Some("fetch_request"),
+            },
+        ];
+        let ads_host = Host::parse("ads.example.com").unwrap();
+        let blocklist = Blocklist {
+            hosts: vec![ads_host.clone()],
+            provenance: [(ads_host, vec![0])].into_iter().collect(),
+            ..Blocklist::default()
+        };
+        let mut metrics = BuildMetrics::default();
+
+        // act
+        metrics.record_fetches(&sources, &stats);
+        metrics.record_build(&blocklist, UNIX_EPOCH + Duration::from_secs(60));
+        let result = metrics.render();
+
+        // assert
+        assert!(result.contains("\nblocklist_generator_domains 1\n"));
+        assert!(result.contains(
+            "\nblocklist_generator_source_domains{source=\"yoyo\",url=\"https://pgl.yoyo.org/adservers/serverlist.php\"} 1\n"
+        ));
+        assert!(result.contains(
+            "\nblocklist_generator_source_fetch_duration_seconds{source=\"yoyo\"} 1.5\n"
+        ));
+        assert!(result.contains("\nblocklist_generator_source_bytes{source=\"yoyo\"} 2048\n"));
+        assert!(result.contains(
+            "\nblocklist_generator_fetch_failures_total{error_kind=\"fetch_request\"} 1\n"
+        ));
+        assert!(result.contains("\nblocklist_generator_last_build_failed 0\n"));
+        assert!(result.ends_with("\nblocklist_generator_last_success_timestamp_seconds 60\n"));
+    }
+
+    #[test]
+    fn render_keeps_last_good_source_domains_after_failed_build() {
+        // arrange
+        let sources = [Source {
+            name: String::from("yoyo"),
+            url: "https://pgl.yoyo.org/adservers/serverlist.php",
+            source_type: SourceType::DomainList,
+            category: None,
+            weight: 1,
+        }];
+        let ads_host = Host::parse("ads.example.com").unwrap();
+        let blocklist = Blocklist {
+            hosts: vec![ads_host.clone()],
+            provenance: [(ads_host, vec![0])].into_iter().collect(),
+            ..Blocklist::default()
+        };
+        let failed_stats = [FetchStats {
+            url: String::from(sources[0].url),
+            duration: Duration::from_millis(100),
+            bytes: 0,
+            error_kind: Some("fetch_request"),
+        }];
+        let mut metrics = BuildMetrics::default();
+        metrics.record_fetches(&sources, &[]);
+        metrics.record_build(&blocklist, UNIX_EPOCH + Duration::from_secs(60));
+
+        // act
+        metrics.record_fetches(&sources, &failed_stats);
+        metrics.record_failure();
+        let result = metrics.render();
+
+        // assert
+        assert!(result.contains(
+            "\nblocklist_generator_source_domains{source=\"yoyo\",url=\"https://pgl.yoyo.org/adservers/serverlist.php\"} 1\n"
+        ));
+        assert!(result.contains("\nblocklist_generator_last_build_failed 1\n"));
+        assert!(result.ends_with("\nblocklist_generator_last_success_timestamp_seconds 60\n"));
+    }
+}
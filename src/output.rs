@@ -0,0 +1,824 @@
+use crate::{pattern::NamePattern, Blocklist};
+use askama::Template;
+use ipnet::IpNet;
+use log::warn;
+use serde::{Deserialize, Serialize};
+use std::{borrow::Cow, fmt::Write};
+use url::Host;
+
+#[derive(Clone, Copy, Debug, Deserialize, Eq, Hash, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// BIND Response Policy Zone, blocking each domain and its subdomains
+    Rpz,
+
+    /// Hosts file, mapping each domain to `0.0.0.0`
+    Hosts,
+
+    /// dnsmasq config, blocking each domain and its subdomains
+    Dnsmasq,
+
+    /// Pi-hole adlist, listing one domain per line, for Pi-hole to fetch as a single source
+    Adlist,
+
+    /// nftables sets of blocked IP addresses and ranges, for firewall rules
+    Nftables,
+
+    /// ipset restore file of blocked IP addresses and ranges, for iptables rules
+    Ipset,
+
+    /// JSON array of blocked hosts, with their sources, categories and first seen time
+    Json,
+
+    /// Newline-delimited JSON, with one blocked host object per line
+    Ndjson,
+
+    /// CSV of blocked hosts, with their sources, categories and first seen time
+    Csv,
+}
+
+/// Blocked host in JSON and CSV output.
+#[derive(Serialize)]
+struct HostRecord<'a> {
+    host: String,
+    sources: Vec<&'a str>,
+    categories: Vec<&'a str>,
+
+    /// RFC 3339 time, or `null` if unknown
+    first_seen: Option<String>,
+}
+
+/// Host field of a previously written JSON record.
+#[derive(Deserialize)]
+struct HostRecordHost<'a> {
+    host: &'a str,
+}
+
+const CSV_HEADER: &str = "host,sources,categories,first_seen";
+
+#[derive(Template)]
+#[template(escape = "none", path = "blocklist.rpz")]
+struct BlocklistRPZTemplate<'a> {
+    domains: &'a str,
+}
+
+#[derive(Template)]
+#[template(escape = "none", path = "blocklist.hosts")]
+struct BlocklistHostsTemplate<'a> {
+    domains: &'a str,
+}
+
+#[derive(Template)]
+#[template(escape = "none", path = "blocklist.dnsmasq.conf")]
+struct BlocklistDnsmasqTemplate<'a> {
+    domains: &'a str,
+}
+
+#[derive(Template)]
+#[template(escape = "none", path = "blocklist.adlist.txt")]
+struct BlocklistAdlistTemplate<'a> {
+    domains: &'a str,
+}
+
+#[derive(Template)]
+#[template(escape = "none", path = "blocklist.nft")]
+struct BlocklistNftablesTemplate<'a> {
+    sets: &'a str,
+}
+
+#[derive(Template)]
+#[template(escape = "none", path = "blocklist.ipset")]
+struct BlocklistIpsetTemplate<'a> {
+    sets: &'a str,
+}
+
+/// Owner names of the RPZ records blocking the host and its subdomains.
+fn rpz_domain_owners(host: &Host) -> [String; 2] {
+    let domain = host.to_string();
+    [domain.clone(), format!("*.{domain}")]
+}
+
+/// Owner name of the RPZ record blocking names matching `pattern`, if RPZ can express it.
+fn rpz_pattern_owner(pattern: &NamePattern) -> Option<String> {
+    pattern.subdomain_wildcard().map(|val| format!("*.{val}"))
+}
+
+/// Owner names of the RPZ `rpz-ip` triggers, then the `rpz-client-ip` triggers.
+fn rpz_network_owners(response_networks: &[IpNet], client_networks: &[IpNet]) -> Vec<String> {
+    response_networks
+        .iter()
+        .map(|val| format!("{}.rpz-ip", rpz_ip_trigger_name(val)))
+        .chain(
+            client_networks
+                .iter()
+                .map(|val| format!("{}.rpz-client-ip", rpz_ip_trigger_name(val))),
+        )
+        .collect()
+}
+
+/// Owner names, relative to the zone origin, of every record in the RPZ output for the blocklist,
+/// in output order.  Each record is a `CNAME .` blocking the name.
+#[must_use]
+pub fn rpz_owner_names(blocklist: &Blocklist) -> Vec<String> {
+    let mut result: Vec<String> = blocklist.hosts.iter().flat_map(rpz_domain_owners).collect();
+    result.extend(blocklist.patterns.iter().filter_map(rpz_pattern_owner));
+    result.extend(rpz_network_owners(
+        &blocklist.response_ip_networks,
+        &blocklist.client_ip_networks,
+    ));
+    result
+}
+
+fn domain_to_blocklist_rpz_domain(host: &Host) -> String {
+    rpz_domain_owners(host)
+        .iter()
+        .fold(String::new(), |mut acc, val| {
+            let _ = writeln!(acc, "{val}\tCNAME\t.");
+            acc
+        })
+}
+
+fn domain_to_blocklist_hosts_domain(host: &Host) -> String {
+    format!("0.0.0.0 {host}\n")
+}
+
+fn domain_to_blocklist_dnsmasq_domain(host: &Host) -> String {
+    format!("address=/{host}/#\n")
+}
+
+fn domain_to_blocklist_adlist_domain(host: &Host) -> String {
+    format!("{host}\n")
+}
+
+/// Owner name for an RPZ IP trigger, without the `rpz-ip` or `rpz-client-ip` label: the prefix
+/// length, then the address labels in reverse order.  IPv6 addresses replace their longest run of
+/// zero groups with `zz`.
+fn rpz_ip_trigger_name(network: &IpNet) -> String {
+    match network {
+        IpNet::V4(value) => {
+            let [octet_0, octet_1, octet_2, octet_3] = value.addr().octets();
+            format!(
+                "{}.{octet_3}.{octet_2}.{octet_1}.{octet_0}",
+                value.prefix_len()
+            )
+        }
+        IpNet::V6(value) => {
+            let segments = value.addr().segments();
+            let (mut zeros_start, mut zeros_length) = (0, 0);
+            let mut run_length = 0;
+            for (index, segment) in segments.iter().enumerate() {
+                if *segment == 0 {
+                    run_length += 1;
+                    if run_length > zeros_length {
+                        zeros_start = index + 1 - run_length;
+                        zeros_length = run_length;
+                    }
+                } else {
+                    run_length = 0;
+                }
+            }
+
+            let mut labels: Vec<String> = Vec::with_capacity(segments.len());
+            let mut index = 0;
+            while index < segments.len() {
+                if zeros_length > 1 && index == zeros_start {
+                    labels.push(String::from("zz"));
+                    index += zeros_length;
+                } else {
+                    labels.push(format!("{:x}", segments[index]));
+                    index += 1;
+                }
+            }
+            labels.reverse();
+            format!("{}.{}", value.prefix_len(), labels.join("."))
+        }
+    }
+}
+
+fn host_record<'a>(host: &Host, blocklist: Option<&'a Blocklist>) -> HostRecord<'a> {
+    HostRecord {
+        host: host.to_string(),
+        sources: blocklist
+            .map(|val| val.host_sources(host))
+            .unwrap_or_default(),
+        categories: blocklist
+            .map(|val| val.host_categories(host))
+            .unwrap_or_default(),
+        first_seen: blocklist
+            .and_then(|val| val.first_seen.get(host))
+            .map(|val| humantime::format_rfc3339_seconds(*val).to_string()),
+    }
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+fn csv_row(record: &HostRecord) -> String {
+    format!(
+        "{},{},{},{}\n",
+        csv_field(&record.host),
+        csv_field(&record.sources.join(";")),
+        csv_field(&record.categories.join(";")),
+        record.first_seen.as_deref().unwrap_or_default()
+    )
+}
+
+fn ipset_set(name: &str, family: &str, networks: &[&IpNet]) -> String {
+    let mut result = format!("create {name} hash:net family {family}\nflush {name}\n");
+    for network in networks {
+        // ipset takes single addresses without a prefix length
+        if network.prefix_len() == network.max_prefix_len() {
+            let _ = writeln!(result, "add {name} {}", network.addr());
+        } else {
+            let _ = writeln!(result, "add {name} {network}");
+        }
+    }
+    result
+}
+
+fn nftables_set(name: &str, address_type: &str, networks: &[&IpNet]) -> String {
+    let mut result =
+        format!("\tset {name} {{\n\t\ttype {address_type}\n\t\tflags interval\n\t\tauto-merge\n");
+    if !networks.is_empty() {
+        let elements: Vec<String> = networks.iter().map(ToString::to_string).collect();
+        let _ = writeln!(result, "\t\telements = {{ {} }}", elements.join(", "));
+    }
+    result.push_str("\t}\n");
+    result
+}
+
+impl OutputFormat {
+    /// Whether the format lists blocked domains, rather than only IP addresses for firewalls.
+    #[must_use]
+    pub fn lists_domains(self) -> bool {
+        !matches!(self, OutputFormat::Nftables | OutputFormat::Ipset)
+    }
+
+    /// Name used in config and metrics labels.
+    #[must_use]
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputFormat::Rpz => "rpz",
+            OutputFormat::Hosts => "hosts",
+            OutputFormat::Dnsmasq => "dnsmasq",
+            OutputFormat::Adlist => "adlist",
+            OutputFormat::Nftables => "nftables",
+            OutputFormat::Ipset => "ipset",
+            OutputFormat::Json => "json",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Csv => "csv",
+        }
+    }
+
+    #[must_use]
+    pub fn file_name(self) -> &'static str {
+        match self {
+            OutputFormat::Rpz => "blocklist.rpz",
+            OutputFormat::Hosts => "blocklist.hosts",
+            OutputFormat::Dnsmasq => "blocklist.dnsmasq.conf",
+            OutputFormat::Adlist => "blocklist.adlist.txt",
+            OutputFormat::Nftables => "blocklist.nft",
+            OutputFormat::Ipset => "blocklist.ipset",
+            OutputFormat::Json => "blocklist.json",
+            OutputFormat::Ndjson => "blocklist.ndjson",
+            OutputFormat::Csv => "blocklist.csv",
+        }
+    }
+
+    #[must_use]
+    pub fn content_type(self) -> &'static str {
+        match self {
+            OutputFormat::Rpz
+            | OutputFormat::Hosts
+            | OutputFormat::Dnsmasq
+            | OutputFormat::Adlist
+            | OutputFormat::Nftables
+            | OutputFormat::Ipset => "text/plain; charset=utf-8",
+            OutputFormat::Json => "application/json",
+            OutputFormat::Ndjson => "application/x-ndjson",
+            OutputFormat::Csv => "text/csv; charset=utf-8",
+        }
+    }
+
+    /// Renders the complete output file.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the template fails to render, which is not expected.
+    #[must_use]
+    pub fn render(self, blocklist_domains: &[Host]) -> String {
+        self.render_parts(blocklist_domains, None, false)
+    }
+
+    /// Renders the complete output file for a merged blocklist, including any custom patterns and
+    /// IP networks the format can express.  With `source_comments`, each RPZ record and adlist
+    /// domain is preceded by a `sources:` comment naming the sources which list it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the template fails to render, which is not expected.
+    #[must_use]
+    pub fn render_blocklist(self, blocklist: &Blocklist, source_comments: bool) -> String {
+        self.render_parts(&blocklist.hosts, Some(blocklist), source_comments)
+    }
+
+    /// Output line blocking names matching `pattern`, if the format can express it.
+    fn pattern_line(self, pattern: &NamePattern) -> Option<String> {
+        match self {
+            OutputFormat::Rpz => rpz_pattern_owner(pattern).map(|val| format!("{val}\tCNAME\t.\n")),
+            OutputFormat::Hosts
+            | OutputFormat::Adlist
+            | OutputFormat::Json
+            | OutputFormat::Ndjson
+            | OutputFormat::Csv => None,
+            OutputFormat::Dnsmasq => pattern
+                .leading_wildcard_glob()
+                .map(|val| format!("address=/{val}/#\n")),
+            OutputFormat::Nftables | OutputFormat::Ipset => Some(String::new()),
+        }
+    }
+
+    fn pattern_lines(self, patterns: &[NamePattern]) -> String {
+        let mut result = String::new();
+        for pattern in patterns {
+            if let Some(value) = self.pattern_line(pattern) {
+                result.push_str(&value);
+            } else {
+                warn!(
+                    "The {} output format cannot express pattern `{}`, so only matching hosts listed by sources are blocked",
+                    self.name(),
+                    pattern.to_source()
+                );
+            }
+        }
+        result
+    }
+
+    /// Output lines blocking IP networks: RPZ `rpz-ip` and `rpz-client-ip` triggers, or firewall
+    /// sets, including any addresses resolved for blocked domains.
+    fn network_lines(self, blocklist: Option<&Blocklist>) -> String {
+        let (response_networks, client_networks, resolved_addresses) =
+            blocklist.map_or((&[][..], &[][..], &[][..]), |val| {
+                (
+                    &val.response_ip_networks[..],
+                    &val.client_ip_networks[..],
+                    &val.resolved_addresses[..],
+                )
+            });
+        match self {
+            OutputFormat::Rpz => rpz_network_owners(response_networks, client_networks)
+                .iter()
+                .fold(String::new(), |mut acc, val| {
+                    let _ = writeln!(acc, "{val}\tCNAME\t.");
+                    acc
+                }),
+            OutputFormat::Hosts
+            | OutputFormat::Dnsmasq
+            | OutputFormat::Adlist
+            | OutputFormat::Json
+            | OutputFormat::Ndjson
+            | OutputFormat::Csv => {
+                if !response_networks.is_empty() || !client_networks.is_empty() {
+                    warn!(
+                        "The {} output format cannot express IP address triggers, so IP list sources are skipped",
+                        self.name()
+                    );
+                }
+                String::new()
+            }
+            OutputFormat::Nftables | OutputFormat::Ipset => {
+                // addresses inside a blocked range are already dropped by the blocked set
+                let resolved_networks: Vec<IpNet> = resolved_addresses
+                    .iter()
+                    .map(|val| IpNet::from(*val))
+                    .filter(|val| {
+                        !response_networks
+                            .iter()
+                            .any(|network| network.contains(val))
+                    })
+                    .collect();
+                let mut sets: Vec<String> = Vec::with_capacity(6);
+                for (name, networks) in [
+                    ("blocked", response_networks),
+                    ("blocked_clients", client_networks),
+                    ("resolved", &resolved_networks[..]),
+                ] {
+                    // interval sets reject overlapping elements, so merge them first
+                    let networks = IpNet::aggregate(&networks.to_vec());
+                    let (ipv4, ipv6): (Vec<&IpNet>, Vec<&IpNet>) =
+                        networks.iter().partition(|val| matches!(val, IpNet::V4(_)));
+                    if self == OutputFormat::Nftables {
+                        sets.push(nftables_set(&format!("{name}_ipv4"), "ipv4_addr", &ipv4));
+                        sets.push(nftables_set(&format!("{name}_ipv6"), "ipv6_addr", &ipv6));
+                    } else {
+                        sets.push(ipset_set(&format!("{name}_ipv4"), "inet", &ipv4));
+                        sets.push(ipset_set(&format!("{name}_ipv6"), "inet6", &ipv6));
+                    }
+                }
+                let separator = if self == OutputFormat::Nftables {
+                    "\n"
+                } else {
+                    ""
+                };
+                sets.join(separator)
+            }
+        }
+    }
+
+    fn render_parts(
+        self,
+        blocklist_domains: &[Host],
+        blocklist: Option<&Blocklist>,
+        source_comments: bool,
+    ) -> String {
+        let line = match self {
+            OutputFormat::Rpz => domain_to_blocklist_rpz_domain,
+            OutputFormat::Hosts => domain_to_blocklist_hosts_domain,
+            OutputFormat::Dnsmasq => domain_to_blocklist_dnsmasq_domain,
+            OutputFormat::Adlist => domain_to_blocklist_adlist_domain,
+            OutputFormat::Nftables | OutputFormat::Ipset => |_: &Host| String::new(),
+            OutputFormat::Json | OutputFormat::Ndjson | OutputFormat::Csv => {
+                return self.render_records(blocklist_domains, blocklist);
+            }
+        };
+        let mut domains = blocklist_domains
+            .iter()
+            .fold(String::new(), |mut acc, val| {
+                let comment_prefix = match self {
+                    OutputFormat::Rpz => Some(';'),
+                    OutputFormat::Adlist => Some('#'),
+                    _ => None,
+                };
+                if let (Some(prefix), Some(value), true) =
+                    (comment_prefix, blocklist, source_comments)
+                {
+                    let _ = writeln!(
+                        acc,
+                        "{prefix} sources: {}",
+                        value.host_sources(val).join(", ")
+                    );
+                }
+                acc.push_str(&line(val));
+                acc
+            });
+        if let Some(value) = blocklist {
+            domains.push_str(&self.pattern_lines(&value.patterns));
+        }
+        domains.push_str(&self.network_lines(blocklist));
+        let rendered = match self {
+            OutputFormat::Rpz => BlocklistRPZTemplate { domains: &domains }.render(),
+            OutputFormat::Hosts => BlocklistHostsTemplate { domains: &domains }.render(),
+            OutputFormat::Dnsmasq => BlocklistDnsmasqTemplate { domains: &domains }.render(),
+            OutputFormat::Adlist => BlocklistAdlistTemplate { domains: &domains }.render(),
+            OutputFormat::Nftables => BlocklistNftablesTemplate { sets: &domains }.render(),
+            OutputFormat::Ipset => BlocklistIpsetTemplate { sets: &domains }.render(),
+            OutputFormat::Json | OutputFormat::Ndjson | OutputFormat::Csv => Ok(domains),
+        };
+        rendered.expect("Unexpected error rendering template")
+    }
+
+    /// Renders JSON, NDJSON or CSV output, with one record per line, so previous output can be read
+    /// back line by line.
+    fn render_records(self, blocklist_domains: &[Host], blocklist: Option<&Blocklist>) -> String {
+        // neither patterns nor networks can be expressed, so only log a warning for them
+        if let Some(value) = blocklist {
+            self.pattern_lines(&value.patterns);
+        }
+        self.network_lines(blocklist);
+
+        let records = blocklist_domains
+            .iter()
+            .map(|val| host_record(val, blocklist));
+        let to_json = |record: HostRecord| {
+            serde_json::to_string(&record).expect("Unexpected error serialising record")
+        };
+        match self {
+            OutputFormat::Json => {
+                let lines: Vec<String> = records.map(to_json).collect();
+                if lines.is_empty() {
+                    String::from("[]\n")
+                } else {
+                    format!("[\n{}\n]\n", lines.join(",\n"))
+                }
+            }
+            OutputFormat::Ndjson => records.fold(String::new(), |mut acc, val| {
+                let _ = writeln!(acc, "{}", to_json(val));
+                acc
+            }),
+            _ => records.fold(format!("{CSV_HEADER}\n"), |mut acc, val| {
+                acc.push_str(&csv_row(&val));
+                acc
+            }),
+        }
+    }
+
+    /// Extracts the blocked domain from a line of previously rendered output, skipping headers,
+    /// comments, the RPZ `*.` wildcard records and IP triggers, and dnsmasq wildcard patterns.
+    /// Firewall output lists no domains.
+    #[must_use]
+    pub fn parse_domain(self, line: &str) -> Option<&str> {
+        match self {
+            OutputFormat::Rpz => match line.split('\t').collect::<Vec<&str>>()[..] {
+                [domain, "CNAME", "."]
+                    if !domain.starts_with("*.")
+                        && !domain.ends_with(".rpz-ip")
+                        && !domain.ends_with(".rpz-client-ip") =>
+                {
+                    Some(domain)
+                }
+                _ => None,
+            },
+            OutputFormat::Hosts => line.strip_prefix("0.0.0.0 "),
+            OutputFormat::Dnsmasq => line
+                .strip_prefix("address=/")
+                .and_then(|val| val.strip_suffix("/#"))
+                .filter(|val| !val.starts_with('*')),
+            OutputFormat::Adlist => {
+                Some(line).filter(|val| !val.is_empty() && !val.starts_with('#'))
+            }
+            OutputFormat::Nftables | OutputFormat::Ipset => None,
+            OutputFormat::Json | OutputFormat::Ndjson => {
+                serde_json::from_str::<HostRecordHost>(line.trim_end_matches(','))
+                    .ok()
+                    .map(|val| val.host)
+            }
+            OutputFormat::Csv => line
+                .split(',')
+                .next()
+                .filter(|val| !val.is_empty() && line != CSV_HEADER),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::rpz_ip_trigger_name;
+    use super::OutputFormat;
+    use crate::{pattern::NamePattern, Blocklist};
+    use ipnet::IpNet;
+    use std::time::UNIX_EPOCH;
+    use url::Host;
+
+    #[test]
+    fn render_writes_each_domain_in_output_format() {
+        // arrange
+        let domains = [
+            Host::parse("ads.example.com").unwrap(),
+            Host::parse("example.org").unwrap(),
+        ];
+
+        // act
+        let result_0 = OutputFormat::Rpz.render(&domains);
+        let result_1 = OutputFormat::Hosts.render(&domains);
+        let result_2 = OutputFormat::Dnsmasq.render(&domains);
+
+        // assert
+        assert!(result_0.starts_with("$TTL\t60\n"));
+        assert!(result_0.ends_with(
+            "ads.example.com\tCNAME\t.\n*.ads.example.com\tCNAME\t.\nexample.org\tCNAME\t.\n*.example.org\tCNAME\t.\n"
+        ));
+        assert!(result_1.ends_with("\n0.0.0.0 ads.example.com\n0.0.0.0 example.org\n"));
+        assert!(result_2.ends_with("\naddress=/ads.example.com/#\naddress=/example.org/#\n"));
+    }
+
+    #[test]
+    fn render_blocklist_comments_rpz_records_and_adlist_domains() {
+        // arrange
+        let ads_host = Host::parse("ads.example.com").unwrap();
+        let blocklist = Blocklist {
+            hosts: vec![ads_host.clone()],
+            source_names: vec![String::from("yoyo"), String::from("firebog")],
+            provenance: [(ads_host, vec![0, 1])].into_iter().collect(),
+            ..Blocklist::default()
+        };
+
+        // act
+        let result_0 = OutputFormat::Rpz.render_blocklist(&blocklist, true);
+        let result_1 = OutputFormat::Hosts.render_blocklist(&blocklist, true);
+        let result_2 = OutputFormat::Adlist.render_blocklist(&blocklist, true);
+
+        // assert
+        assert!(result_0.ends_with(
+            "\n; sources: yoyo, firebog\nads.example.com\tCNAME\t.\n*.ads.example.com\tCNAME\t.\n"
+        ));
+        assert!(result_1.ends_with("\n0.0.0.0 ads.example.com\n"));
+        assert!(result_2.ends_with("\n# sources: yoyo, firebog\nads.example.com\n"));
+    }
+
+    #[test]
+    fn render_blocklist_emits_expressible_patterns() {
+        // arrange
+        let blocklist = Blocklist {
+            patterns: ["*.example.com", "*tracker*.example.com"]
+                .into_iter()
+                .map(|val| NamePattern::parse(val).unwrap().unwrap())
+                .collect(),
+            ..Blocklist::default()
+        };
+
+        // act
+        let result_0 = OutputFormat::Rpz.render_blocklist(&blocklist, false);
+        let result_1 = OutputFormat::Dnsmasq.render_blocklist(&blocklist, false);
+
+        // assert
+        assert!(result_0.ends_with("\n*.example.com\tCNAME\t.\n"));
+        assert!(result_1.ends_with("\naddress=/*.example.com/#\n"));
+        assert_eq!(
+            OutputFormat::Dnsmasq.parse_domain("address=/*.example.com/#"),
+            None
+        );
+    }
+
+    #[test]
+    fn parse_domain_reads_back_rendered_domains() {
+        // arrange
+        let domains = [
+            Host::parse("ads.example.com").unwrap(),
+            Host::parse("example.org").unwrap(),
+        ];
+
+        for format in [
+            OutputFormat::Rpz,
+            OutputFormat::Hosts,
+            OutputFormat::Dnsmasq,
+            OutputFormat::Adlist,
+            OutputFormat::Json,
+            OutputFormat::Ndjson,
+            OutputFormat::Csv,
+        ] {
+            let rendered = format.render(&domains);
+
+            // act
+            let result: Vec<&str> = rendered
+                .lines()
+                .filter_map(|val| format.parse_domain(val))
+                .collect();
+
+            // assert
+            assert_eq!(result, vec!["ads.example.com", "example.org"]);
+        }
+    }
+
+    #[test]
+    fn render_blocklist_writes_records_with_sources_and_categories() {
+        // arrange
+        let ads_host = Host::parse("ads.example.com").unwrap();
+        let blocklist = Blocklist {
+            hosts: vec![ads_host.clone()],
+            source_names: vec![String::from("yoyo"), String::from("firebog, ads")],
+            source_categories: vec![Some(String::from("ads")), None],
+            first_seen: [(ads_host.clone(), UNIX_EPOCH)].into_iter().collect(),
+            provenance: [(ads_host, vec![0, 1])].into_iter().collect(),
+            ..Blocklist::default()
+        };
+
+        // act
+        let result_0 = OutputFormat::Json.render_blocklist(&blocklist, false);
+        let result_1 = OutputFormat::Ndjson.render_blocklist(&blocklist, false);
+        let result_2 = OutputFormat::Csv.render_blocklist(&blocklist, false);
+
+        // assert
+        let record = r#"{"host":"ads.example.com","sources":["yoyo","firebog, ads"],"categories":["ads"],"first_seen":"1970-01-01T00:00:00Z"}"#;
+        assert_eq!(result_0, format!("[\n{record}\n]\n"));
+        assert_eq!(result_1, format!("{record}\n"));
+        assert_eq!(
+            result_2,
+            "host,sources,categories,first_seen\nads.example.com,\"yoyo;firebog, ads\",ads,1970-01-01T00:00:00Z\n"
+        );
+    }
+
+    #[test]
+    fn render_blocklist_writes_firewall_sets_with_resolved_addresses() {
+        // arrange
+        let blocklist = Blocklist {
+            response_ip_networks: vec!["198.51.100.0/24".parse().unwrap()],
+            resolved_addresses: vec!["192.0.2.1".parse().unwrap()],
+            ..Blocklist::default()
+        };
+
+        // act
+        let result_0 = OutputFormat::Ipset.render_blocklist(&blocklist, false);
+        let result_1 = OutputFormat::Nftables.render_blocklist(&blocklist, false);
+
+        // assert
+        assert!(result_0.contains(
+            "create blocked_ipv4 hash:net family inet\nflush blocked_ipv4\nadd blocked_ipv4 198.51.100.0/24\n"
+        ));
+        assert!(result_0.ends_with(
+            "create resolved_ipv4 hash:net family inet\nflush resolved_ipv4\nadd resolved_ipv4 192.0.2.1\ncreate resolved_ipv6 hash:net family inet6\nflush resolved_ipv6\n"
+        ));
+        assert!(result_1.contains("\tset resolved_ipv4 {\n\t\ttype ipv4_addr\n\t\tflags interval\n\t\tauto-merge\n\t\telements = { 192.0.2.1/32 }\n\t}\n"));
+    }
+
+    #[test]
+    fn render_blocklist_merges_overlapping_firewall_networks() {
+        // arrange
+        let blocklist = Blocklist {
+            response_ip_networks: vec![
+                "198.51.100.0/24".parse().unwrap(),
+                "198.51.100.7/32".parse().unwrap(),
+                "198.51.101.0/24".parse().unwrap(),
+            ],
+            ..Blocklist::default()
+        };
+
+        // act
+        let result_0 = OutputFormat::Nftables.render_blocklist(&blocklist, false);
+        let result_1 = OutputFormat::Ipset.render_blocklist(&blocklist, false);
+
+        // assert
+        assert!(result_0.contains("\tset blocked_ipv4 {\n\t\ttype ipv4_addr\n\t\tflags interval\n\t\tauto-merge\n\t\telements = { 198.51.100.0/23 }\n\t}\n"));
+        assert!(result_1.contains(
+            "create blocked_ipv4 hash:net family inet\nflush blocked_ipv4\nadd blocked_ipv4 198.51.100.0/23\ncreate"
+        ));
+    }
+
+    #[test]
+    fn render_blocklist_leaves_blocked_addresses_out_of_resolved_set() {
+        // arrange
+        let blocklist = Blocklist {
+            response_ip_networks: vec!["198.51.100.0/24".parse().unwrap()],
+            resolved_addresses: vec![
+                "198.51.100.7".parse().unwrap(),
+                "192.0.2.1".parse().unwrap(),
+            ],
+            ..Blocklist::default()
+        };
+
+        // act
+        let result = OutputFormat::Nftables.render_blocklist(&blocklist, false);
+
+        // assert
+        assert!(result.contains("\tset resolved_ipv4 {\n\t\ttype ipv4_addr\n\t\tflags interval\n\t\tauto-merge\n\t\telements = { 192.0.2.1/32 }\n\t}\n"));
+    }
+
+    #[test]
+    fn rpz_ip_trigger_name_reverses_address_labels() {
+        // arrange
+        let networks: Vec<IpNet> = [
+            "10.0.0.1/32",
+            "198.51.100.0/24",
+            "2001:2:3::1/128",
+            "2001:db8::/32",
+        ]
+        .into_iter()
+        .map(|val| val.parse().unwrap())
+        .collect();
+
+        // act
+        let result: Vec<String> = networks.iter().map(rpz_ip_trigger_name).collect();
+
+        // assert
+        assert_eq!(
+            result,
+            vec![
+                "32.1.0.0.10",
+                "24.0.100.51.198",
+                "128.1.zz.3.2.2001",
+                "32.zz.db8.2001"
+            ]
+        );
+    }
+
+    #[test]
+    fn render_blocklist_writes_ip_networks() {
+        // arrange
+        let blocklist = Blocklist {
+            response_ip_networks: vec![
+                "192.0.2.1/32".parse().unwrap(),
+                "2001:db8::/32".parse().unwrap(),
+            ],
+            client_ip_networks: vec!["198.51.100.0/24".parse().unwrap()],
+            ..Blocklist::default()
+        };
+
+        // act
+        let result_0 = OutputFormat::Rpz.render_blocklist(&blocklist, false);
+        let result_1 = OutputFormat::Nftables.render_blocklist(&blocklist, false);
+
+        // assert
+        assert!(result_0.ends_with(
+            "\n32.1.2.0.192.rpz-ip\tCNAME\t.\n32.zz.db8.2001.rpz-ip\tCNAME\t.\n24.0.100.51.198.rpz-client-ip\tCNAME\t.\n"
+        ));
+        assert!(result_1.contains(
+            "\tset blocked_ipv4 {\n\t\ttype ipv4_addr\n\t\tflags interval\n\t\tauto-merge\n\t\telements = { 192.0.2.1/32 }\n\t}\n"
+        ));
+        assert!(result_1.contains(
+            "\tset resolved_ipv6 {\n\t\ttype ipv6_addr\n\t\tflags interval\n\t\tauto-merge\n\t}\n}"
+        ));
+        assert!(result_1.ends_with("}\n"));
+        assert_eq!(
+            result_0
+                .lines()
+                .filter_map(|val| OutputFormat::Rpz.parse_domain(val))
+                .count(),
+            0
+        );
+    }
+}
@@ -1,4 +1,5 @@
 use ahash::RandomState;
+use ipnet::IpNet;
 use log::trace;
 use nom::{
     branch::alt,
@@ -9,9 +10,14 @@ use nom::{
     sequence::{pair, tuple},
     IResult,
 };
-use std::collections::HashSet;
+use std::{
+    collections::{BTreeSet, HashSet},
+    net::IpAddr,
+};
 use url::Host;
 
+use crate::SourceType;
+
 fn is_digit(c: char) -> bool {
     c.is_ascii_digit()
 }
@@ -64,11 +70,62 @@ fn parse_hostfile_line(input: &str) -> Option<&str> {
     Some(hostname)
 }
 
-pub fn domainlist(file_body: &str, set: &mut HashSet<Host, RandomState>) {
+/// Hostname listed on a single line of a source, if any.
+pub(crate) fn line_hostname(source_type: SourceType, line: &str) -> Option<&str> {
+    match source_type {
+        SourceType::DomainList => parse_domainlist_line(line),
+        SourceType::HostsFile => parse_hostfile_line(line),
+        SourceType::IpList | SourceType::ClientIpList => None,
+    }
+}
+
+fn parse_iplist_line(line: &str) -> Option<IpNet> {
+    // expect "192.0.2.1", "198.51.100.0/24" or "2001:db8::/32", optionally followed by a comment
+    let value = line.split(['#', ';']).next()?.split_whitespace().next()?;
+    value
+        .parse::<IpNet>()
+        .or_else(|_| value.parse::<IpAddr>().map(IpNet::from))
+        .ok()
+        .map(|val| val.trunc())
+}
+
+/// Whether `set` has grown past `max_len` entries, if given.
+fn exceeds(len: usize, max_len: Option<usize>) -> bool {
+    max_len.is_some_and(|val| len > val)
+}
+
+/// Adds each IP address and CIDR range in a list, one per line, to `set`.  Comments and
+/// unparsable lines are skipped.  Stops as soon as `set` holds more than `max_len` entries,
+/// returning `false`.
+pub fn iplist(file_body: &str, set: &mut BTreeSet<IpNet>, max_len: Option<usize>) -> bool {
+    for line in file_body.lines() {
+        if let Some(value) = parse_iplist_line(line) {
+            set.insert(value);
+            if exceeds(set.len(), max_len) {
+                return false;
+            }
+        } else if !line.trim().is_empty() && !line.trim_start().starts_with(['#', ';']) {
+            trace!("Unable to parse `{line}`");
+        }
+    }
+    true
+}
+
+/// Adds each valid hostname in a list of domains, one per line, to `set`.  Comments and
+/// unparsable lines are skipped.  Stops as soon as `set` holds more than `max_len` entries,
+/// returning `false`.
+pub fn domainlist(
+    file_body: &str,
+    set: &mut HashSet<Host, RandomState>,
+    max_len: Option<usize>,
+) -> bool {
     for line in file_body.lines() {
         if let Some(value) = parse_domainlist_line(line) {
             if let Ok(host_value) = Host::parse(value) {
                 set.insert(host_value);
+                if exceeds(set.len(), max_len) {
+                    return false;
+                }
             } else {
                 trace!("Unable to parse hostname in line `{value}`");
             }
@@ -76,13 +133,23 @@ pub fn domainlist(file_body: &str, set: &mut HashSet<Host, RandomState>) {
             trace!("Unable to parse `{line}`");
         }
     }
+    true
 }
 
-pub fn hostfile(file_body: &str, set: &mut HashSet<Host, RandomState>) {
+/// Adds each valid hostname in a hosts file to `set`.  Comments and unparsable lines are skipped.
+/// Stops as soon as `set` holds more than `max_len` entries, returning `false`.
+pub fn hostfile(
+    file_body: &str,
+    set: &mut HashSet<Host, RandomState>,
+    max_len: Option<usize>,
+) -> bool {
     for line in file_body.lines() {
         if let Some(value) = parse_hostfile_line(line) {
             if let Ok(host_value) = Host::parse(value) {
                 set.insert(host_value);
+                if exceeds(set.len(), max_len) {
+                    return false;
+                }
             } else {
                 trace!("Unable to parse hostname in line `{value}`");
             }
@@ -90,13 +157,14 @@ pub fn hostfile(file_body: &str, set: &mut HashSet<Host, RandomState>) {
             trace!("Unable to parse `{line}`");
         }
     }
+    true
 }
 
 #[cfg(test)]
 mod tests {
-    use std::collections::HashSet;
+    use std::collections::{BTreeSet, HashSet};
 
-    use crate::parse::{domainlist, hostfile, parse_domainlist_line};
+    use crate::parse::{domainlist, hostfile, iplist, parse_domainlist_line};
 
     use super::{parse_hostfile_line, parse_hostname, parse_ipv4_address, parse_ipv4_octet};
     use ahash::RandomState;
@@ -382,7 +450,7 @@ final-example.com";
         let mut hash_set: HashSet<Host, RandomState> = HashSet::with_hasher(hasher);
 
         // act
-        domainlist(input, &mut hash_set);
+        domainlist(input, &mut hash_set, None);
 
         // assert
         assert_eq!(hash_set.len(), 3);
@@ -391,6 +459,21 @@ final-example.com";
         assert!(hash_set.contains(&Host::parse("final-example.com").unwrap()));
     }
 
+    #[test]
+    fn domainlist_stops_once_past_max_len() {
+        // arrange
+        let input = "one.example.com\ntwo.example.com\nthree.example.com\nfour.example.com";
+        let mut hash_set: HashSet<Host, RandomState> = HashSet::default();
+
+        // act
+        let result = domainlist(input, &mut hash_set, Some(2));
+
+        // assert
+        assert!(!result);
+        assert_eq!(hash_set.len(), 3);
+        assert!(!hash_set.contains(&Host::parse("four.example.com").unwrap()));
+    }
+
     #[test]
     fn hostfile_successfully_parses_valid_input() {
         // arrange
@@ -400,7 +483,7 @@ final-example.com";
         let mut hash_set: HashSet<Host, RandomState> = HashSet::with_hasher(hasher);
 
         // act
-        hostfile(input, &mut hash_set);
+        hostfile(input, &mut hash_set, None);
 
         // assert
         assert_eq!(hash_set.len(), 3);
@@ -408,4 +491,22 @@ final-example.com";
         assert!(hash_set.contains(&Host::parse("another-example.com").unwrap()));
         assert!(hash_set.contains(&Host::parse("final-example.com").unwrap()));
     }
+
+    #[test]
+    fn iplist_parses_addresses_and_cidr_ranges() {
+        // arrange
+        let file_body =
+            "# threat feed\n192.0.2.1\n198.51.100.7/24 ; botnet\n2001:db8::/32\nnot-an-address\n\n";
+        let mut set = BTreeSet::new();
+
+        // act
+        iplist(file_body, &mut set, None);
+
+        // assert
+        let result: Vec<String> = set.iter().map(ToString::to_string).collect();
+        assert_eq!(
+            result,
+            vec!["192.0.2.1/32", "198.51.100.0/24", "2001:db8::/32"]
+        );
+    }
 }
@@ -0,0 +1,143 @@
+use regex::Regex;
+
+#[derive(thiserror::Error, Debug)]
+pub enum PatternError {
+    #[error("Invalid pattern `{pattern}`: {message}")]
+    Invalid { pattern: String, message: String },
+}
+
+/// A custom rule blocking every name it matches, rather than one literal name.
+#[derive(Debug)]
+pub enum NamePattern {
+    /// Regular expression, written between slashes: `/^ads?\d*\./`
+    Regex(Regex),
+
+    /// Shell-style glob, where `*` matches any run of characters and `?` any one character:
+    /// `*tracker*.example.com`
+    Glob { glob: String, regex: Regex },
+}
+
+fn glob_regex(glob: &str) -> String {
+    let mut result = String::from("^");
+    for character in glob.chars() {
+        match character {
+            '*' => result.push_str(".*"),
+            '?' => result.push('.'),
+            _ => result.push_str(&regex::escape(&character.to_string())),
+        }
+    }
+    result.push('$');
+    result
+}
+
+impl NamePattern {
+    /// Parses a custom names file line.  Returns `None` for lines which are not patterns, such as
+    /// literal names and comments.
+    #[must_use]
+    pub fn parse(line: &str) -> Option<Result<Self, PatternError>> {
+        let line = line.trim();
+        let invalid = |error: regex::Error| PatternError::Invalid {
+            pattern: line.to_string(),
+            message: error.to_string(),
+        };
+        if let Some(value) = line.strip_prefix('/').and_then(|val| val.strip_suffix('/')) {
+            return Some(Regex::new(value).map(NamePattern::Regex).map_err(invalid));
+        }
+        if line.starts_with('#') || !line.contains(['*', '?']) {
+            return None;
+        }
+        let glob = line.to_lowercase();
+        Some(
+            Regex::new(&glob_regex(&glob))
+                .map(|regex| NamePattern::Glob { glob, regex })
+                .map_err(invalid),
+        )
+    }
+
+    #[must_use]
+    pub fn is_match(&self, name: &str) -> bool {
+        match self {
+            NamePattern::Regex(regex) | NamePattern::Glob { regex, .. } => regex.is_match(name),
+        }
+    }
+
+    /// The pattern as written in the custom names file.
+    #[must_use]
+    pub fn to_source(&self) -> String {
+        match self {
+            NamePattern::Regex(regex) => format!("/{}/", regex.as_str()),
+            NamePattern::Glob { glob, .. } => glob.clone(),
+        }
+    }
+
+    /// Glob whose only wildcard is a leading `*`, such as `*.example.com` or `*tracker.com`.
+    #[must_use]
+    pub fn leading_wildcard_glob(&self) -> Option<&str> {
+        match self {
+            NamePattern::Glob { glob, .. } => glob
+                .strip_prefix('*')
+                .filter(|val| !val.contains(['*', '?']))
+                .map(|_| glob.as_str()),
+            NamePattern::Regex(_) => None,
+        }
+    }
+
+    /// Domain whose subdomains, and only those, the pattern matches: `example.com` for the glob
+    /// `*.example.com`.
+    #[must_use]
+    pub fn subdomain_wildcard(&self) -> Option<&str> {
+        self.leading_wildcard_glob()?.strip_prefix("*.")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::NamePattern;
+
+    #[test]
+    fn parse_recognises_regex_and_glob_lines() {
+        // arrange
+        let regex_line = r"/^ads?\d*\./";
+        let glob_line = "*tracker*.example.com";
+
+        // act
+        let regex_result = NamePattern::parse(regex_line).unwrap().unwrap();
+        let glob_result = NamePattern::parse(glob_line).unwrap().unwrap();
+        let literal_result = NamePattern::parse("example.com");
+        let comment_result = NamePattern::parse("# *.example.com");
+        let invalid_result = NamePattern::parse("/ads(/").unwrap();
+
+        // assert
+        assert!(regex_result.is_match("ads2.example.com"));
+        assert!(!regex_result.is_match("example.com"));
+        assert!(glob_result.is_match("cdn.mytracker1.example.com"));
+        assert!(!glob_result.is_match("tracker.example.org"));
+        assert_eq!(regex_result.to_source(), regex_line);
+        assert_eq!(glob_result.to_source(), glob_line);
+        assert!(literal_result.is_none());
+        assert!(comment_result.is_none());
+        assert!(invalid_result.is_err());
+    }
+
+    #[test]
+    fn subdomain_wildcard_accepts_leading_wildcard_label_only() {
+        // arrange
+        let subdomains = NamePattern::parse("*.example.com").unwrap().unwrap();
+        let suffix = NamePattern::parse("*tracker.com").unwrap().unwrap();
+        let inner = NamePattern::parse("*tracker*.example.com")
+            .unwrap()
+            .unwrap();
+
+        // act
+        let result_0 = subdomains.subdomain_wildcard();
+        let result_1 = suffix.subdomain_wildcard();
+        let result_2 = suffix.leading_wildcard_glob();
+        let result_3 = inner.leading_wildcard_glob();
+
+        // assert
+        assert_eq!(result_0, Some("example.com"));
+        assert_eq!(result_1, None);
+        assert_eq!(result_2, Some("*tracker.com"));
+        assert_eq!(result_3, None);
+    }
+}
@@ -0,0 +1,249 @@
+use crate::Blocklist;
+use ahash::RandomState;
+use rusqlite::{params, Connection, OpenFlags};
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    path::Path,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use url::Url;
+
+/// `adlist` row for blocked hosts with no recorded source, such as hosts kept from a source
+/// since renamed.  Pi-hole reads the address as an empty list.
+const UNATTRIBUTED_ADLIST_ADDRESS: &str = "file:///dev/null";
+
+const UNATTRIBUTED_ADLIST_COMMENT: &str = "blocklist-generator: no recorded source";
+
+/// The parts of Pi-hole's `gravity.db` schema written here.  Existing Pi-hole databases already
+/// have these tables, with extra columns left at their defaults.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS adlist (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    address TEXT UNIQUE NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT 1,
+    date_added INTEGER NOT NULL DEFAULT (cast(strftime('%s', 'now') AS int)),
+    date_modified INTEGER NOT NULL DEFAULT (cast(strftime('%s', 'now') AS int)),
+    comment TEXT
+);
+CREATE TABLE IF NOT EXISTS gravity (
+    domain TEXT NOT NULL,
+    adlist_id INTEGER NOT NULL REFERENCES adlist (id)
+);
+CREATE TABLE IF NOT EXISTS info (
+    property TEXT PRIMARY KEY,
+    value TEXT NOT NULL
+);
+";
+
+#[derive(thiserror::Error, Debug)]
+pub enum GravityError {
+    #[error("Unable to write gravity database: {0}")]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+/// Domains in the `gravity` table of an existing database.  Missing or unreadable databases give
+/// no domains.
+pub fn read_gravity_domains<P: AsRef<Path>>(path: P) -> HashSet<String, RandomState> {
+    let Ok(connection) = Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY) else {
+        return HashSet::default();
+    };
+    let Ok(mut statement) = connection.prepare("SELECT DISTINCT domain FROM gravity") else {
+        return HashSet::default();
+    };
+    statement
+        .query_map([], |row| row.get::<_, String>(0))
+        .map(|rows| rows.filter_map(Result::ok).collect())
+        .unwrap_or_default()
+}
+
+/// `adlist` address for a source: its URL, or a `file://` URL for a local file, such as
+/// `blocked-names.txt`, so Pi-hole does not try to download it.
+fn adlist_address(source_url: &str) -> String {
+    if Url::parse(source_url).is_ok() {
+        return source_url.to_string();
+    }
+    let path = Path::new(source_url);
+    let absolute_path = if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        env::current_dir().map_or_else(|_| path.to_path_buf(), |val| val.join(path))
+    };
+    Url::from_file_path(&absolute_path).map_or_else(|()| source_url.to_string(), String::from)
+}
+
+/// Inserts the `adlist` row for `address`, or updates its comment, returning the row ID.
+fn upsert_adlist(
+    transaction: &rusqlite::Transaction,
+    address: &str,
+    comment: &str,
+) -> Result<i64, GravityError> {
+    transaction.execute(
+        "INSERT INTO adlist (address, comment) VALUES (?1, ?2)
+         ON CONFLICT (address) DO UPDATE SET comment = excluded.comment",
+        params![address, comment],
+    )?;
+    let adlist_id = transaction.query_row(
+        "SELECT id FROM adlist WHERE address = ?1",
+        params![address],
+        |row| row.get(0),
+    )?;
+    Ok(adlist_id)
+}
+
+/// Replaces the `gravity` table with the blocked domains, adding an `adlist` row for each source,
+/// so Pi-hole reports which sources list each domain.  Sources keep their `adlist` ID across
+/// updates, matched on URL.  Domains with no recorded source are listed against a separate
+/// `adlist` row.
+///
+/// # Errors
+///
+/// Returns an error if the database cannot be opened or written.
+pub fn write_gravity_database<P: AsRef<Path>>(
+    path: P,
+    blocklist: &Blocklist,
+) -> Result<(), GravityError> {
+    let mut connection = Connection::open(path)?;
+    connection.execute_batch(SCHEMA)?;
+    let transaction = connection.transaction()?;
+
+    let mut adlist_ids: HashMap<usize, i64, RandomState> = HashMap::default();
+    for (source_id, (source_url, name)) in blocklist
+        .source_urls
+        .iter()
+        .zip(&blocklist.source_names)
+        .enumerate()
+    {
+        let adlist_id = upsert_adlist(&transaction, &adlist_address(source_url), name)?;
+        adlist_ids.insert(source_id, adlist_id);
+    }
+    let host_adlist_ids = |host| -> Vec<i64> {
+        blocklist
+            .provenance
+            .get(host)
+            .map_or(&[][..], Vec::as_slice)
+            .iter()
+            .filter_map(|id| adlist_ids.get(id).copied())
+            .collect()
+    };
+    let unattributed_adlist_id = if blocklist
+        .hosts
+        .iter()
+        .any(|val| host_adlist_ids(val).is_empty())
+    {
+        Some(upsert_adlist(
+            &transaction,
+            UNATTRIBUTED_ADLIST_ADDRESS,
+            UNATTRIBUTED_ADLIST_COMMENT,
+        )?)
+    } else {
+        None
+    };
+
+    transaction.execute("DELETE FROM gravity", [])?;
+    {
+        let mut statement =
+            transaction.prepare("INSERT INTO gravity (domain, adlist_id) VALUES (?1, ?2)")?;
+        for host in &blocklist.hosts {
+            let domain = host.to_string();
+            let mut host_ids = host_adlist_ids(host);
+            if host_ids.is_empty() {
+                host_ids.extend(unattributed_adlist_id);
+            }
+            for adlist_id in host_ids {
+                statement.execute(params![domain, adlist_id])?;
+            }
+        }
+    }
+
+    let updated = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |val| val.as_secs());
+    transaction.execute(
+        "INSERT OR REPLACE INTO info (property, value) VALUES ('updated', ?1)",
+        params![updated.to_string()],
+    )?;
+    transaction.commit()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adlist_address, read_gravity_domains, write_gravity_database};
+    use crate::Blocklist;
+    use rusqlite::Connection;
+    use std::env;
+    use url::{Host, Url};
+
+    #[test]
+    fn write_gravity_database_records_domains_against_each_source() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("gravity.db");
+        let hosts = vec![
+            Host::parse("ads.example.com").unwrap(),
+            Host::parse("retained.example.com").unwrap(),
+            Host::parse("tracker.example.com").unwrap(),
+        ];
+        let blocklist = Blocklist {
+            provenance: [(hosts[0].clone(), vec![0, 1]), (hosts[2].clone(), vec![1])]
+                .into_iter()
+                .collect(),
+            hosts,
+            source_names: vec![String::from("yoyo"), String::from("custom")],
+            source_urls: vec![
+                String::from("https://pgl.yoyo.org/adservers/serverlist.php"),
+                String::from("blocked-names.txt"),
+            ],
+            ..Blocklist::default()
+        };
+
+        // act
+        write_gravity_database(&path, &blocklist).unwrap();
+        write_gravity_database(&path, &blocklist).unwrap();
+
+        // assert
+        let connection = Connection::open(&path).unwrap();
+        let mut statement = connection
+            .prepare(
+                "SELECT domain, comment FROM gravity
+                 JOIN adlist ON adlist.id = gravity.adlist_id ORDER BY domain, comment",
+            )
+            .unwrap();
+        let rows: Vec<(String, String)> = statement
+            .query_map([], |row| Ok((row.get(0).unwrap(), row.get(1).unwrap())))
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                (String::from("ads.example.com"), String::from("custom")),
+                (String::from("ads.example.com"), String::from("yoyo")),
+                (
+                    String::from("retained.example.com"),
+                    String::from("blocklist-generator: no recorded source")
+                ),
+                (String::from("tracker.example.com"), String::from("custom")),
+            ]
+        );
+        assert_eq!(read_gravity_domains(&path).len(), 3);
+    }
+
+    #[test]
+    fn adlist_address_gives_local_files_file_urls() {
+        // arrange
+        let expected = Url::from_file_path(env::current_dir().unwrap().join("blocked-names.txt"))
+            .unwrap()
+            .to_string();
+
+        // act
+        let result_0 = adlist_address("https://pgl.yoyo.org/adservers/serverlist.php");
+        let result_1 = adlist_address("blocked-names.txt");
+
+        // assert
+        assert_eq!(result_0, "https://pgl.yoyo.org/adservers/serverlist.php");
+        assert_eq!(result_1, expected);
+        assert!(result_1.starts_with("file:///"));
+    }
+}
@@ -0,0 +1,226 @@
+use ahash::RandomState;
+use log::warn;
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::Path,
+};
+use url::Host;
+
+/// Copy of <https://publicsuffix.org/list/public_suffix_list.dat>, used unless a local list is
+/// configured
+const BUNDLED_LIST: &str = include_str!("../data/public_suffix_list.dat");
+
+/// Rules from the Public Suffix List, identifying names under which anyone may register domains,
+/// such as `co.uk` or `github.io`.
+pub struct PublicSuffixList {
+    rules: HashSet<String, RandomState>,
+
+    /// Names whose every child is a public suffix, from `*.` rules
+    wildcards: HashSet<String, RandomState>,
+
+    /// Names excluded from a wildcard rule, from `!` rules
+    exceptions: HashSet<String, RandomState>,
+}
+
+/// Rule name in the same ASCII form `Host::parse` gives hostnames.
+fn normalise_rule(rule: &str) -> String {
+    match Host::parse(rule) {
+        Ok(Host::Domain(value)) => value,
+        _ => rule.to_lowercase(),
+    }
+}
+
+impl PublicSuffixList {
+    /// Parses a list in the `public_suffix_list.dat` format.
+    #[must_use]
+    pub fn parse(content: &str) -> Self {
+        let mut result = PublicSuffixList {
+            rules: HashSet::default(),
+            wildcards: HashSet::default(),
+            exceptions: HashSet::default(),
+        };
+        for line in content.lines() {
+            let Some(rule) = line.split_whitespace().next() else {
+                continue;
+            };
+            if rule.starts_with("//") {
+                continue;
+            }
+            if let Some(value) = rule.strip_prefix('!') {
+                result.exceptions.insert(normalise_rule(value));
+            } else if let Some(value) = rule.strip_prefix("*.") {
+                result.wildcards.insert(normalise_rule(value));
+            } else {
+                result.rules.insert(normalise_rule(rule));
+            }
+        }
+        result
+    }
+
+    /// The list bundled with this release.
+    #[must_use]
+    pub fn bundled() -> Self {
+        PublicSuffixList::parse(BUNDLED_LIST)
+    }
+
+    /// Loads a local copy of the list, falling back to the bundled list if it cannot be read.
+    pub fn load<P: AsRef<Path>>(path: Option<P>) -> Self {
+        let Some(path) = path else {
+            return PublicSuffixList::bundled();
+        };
+        match fs::read_to_string(&path) {
+            Ok(value) => PublicSuffixList::parse(&value),
+            Err(error) => {
+                warn!(
+                    "Unable to read Public Suffix List `{}`, using bundled list: {error}",
+                    path.as_ref().display()
+                );
+                PublicSuffixList::bundled()
+            }
+        }
+    }
+
+    /// Number of trailing labels of `name` forming its public suffix.  Names matching no rule
+    /// have their last label as public suffix.
+    fn suffix_label_count(&self, name: &str) -> usize {
+        let labels: Vec<&str> = name.split('.').collect();
+        for start in 0..labels.len() {
+            let candidate = labels[start..].join(".");
+            let label_count = labels.len() - start;
+            if self.exceptions.contains(&candidate) {
+                return label_count - 1;
+            }
+            if self.rules.contains(&candidate) {
+                return label_count;
+            }
+            if label_count > 1 && self.wildcards.contains(&labels[start + 1..].join(".")) {
+                return label_count;
+            }
+        }
+        1
+    }
+
+    /// Whether `name` is itself a public suffix, so blocking it and its subdomains would block
+    /// every site registered under it.
+    #[must_use]
+    pub fn is_public_suffix(&self, name: &str) -> bool {
+        let name = name.trim_end_matches('.');
+        self.suffix_label_count(name) >= name.split('.').count()
+    }
+
+    /// The domain registered under the public suffix of `name`: `example.co.uk` for
+    /// `ads.example.co.uk`.  Returns `None` for public suffixes.
+    #[must_use]
+    pub fn registrable_domain<'a>(&self, name: &'a str) -> Option<&'a str> {
+        let name = name.trim_end_matches('.');
+        let label_count = self.suffix_label_count(name) + 1;
+        let labels: Vec<&str> = name.split('.').collect();
+        if label_count > labels.len() {
+            return None;
+        }
+        let start = labels[..labels.len() - label_count]
+            .iter()
+            .map(|val| val.len() + 1)
+            .sum();
+        Some(&name[start..])
+    }
+
+    /// Counts blocked domains under each registrable domain, largest counts first.
+    #[must_use]
+    pub fn count_by_registrable_domain(&self, hosts: &[Host]) -> Vec<(String, usize)> {
+        let mut counts: HashMap<&str, usize, RandomState> = HashMap::default();
+        for host in hosts {
+            if let Host::Domain(name) = host {
+                if let Some(value) = self.registrable_domain(name) {
+                    *counts.entry(value).or_default() += 1;
+                }
+            }
+        }
+        let mut result: Vec<(String, usize)> = counts
+            .into_iter()
+            .map(|(domain, count)| (domain.to_string(), count))
+            .collect();
+        result.sort_unstable_by(|(domain_0, count_0), (domain_1, count_1)| {
+            count_1.cmp(count_0).then_with(|| domain_0.cmp(domain_1))
+        });
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PublicSuffixList;
+    use url::Host;
+
+    #[test]
+    fn is_public_suffix_applies_rules_wildcards_and_exceptions() {
+        // arrange
+        let list = PublicSuffixList::bundled();
+
+        // act
+        let results: Vec<bool> = [
+            "co.uk",
+            "github.io",
+            "example.co.uk",
+            "user.github.io",
+            "anything.ck",
+            "www.ck",
+            "com",
+        ]
+        .into_iter()
+        .map(|val| list.is_public_suffix(val))
+        .collect();
+
+        // assert
+        assert_eq!(results, vec![true, true, false, false, true, false, true]);
+    }
+
+    #[test]
+    fn registrable_domain_keeps_one_label_below_public_suffix() {
+        // arrange
+        let list = PublicSuffixList::parse("// comment\nuk\nco.uk\n*.ck\n!www.ck\n");
+
+        // act
+        let result_0 = list.registrable_domain("ads.example.co.uk");
+        let result_1 = list.registrable_domain("ads.example.ck");
+        let result_2 = list.registrable_domain("ads.www.ck");
+        let result_3 = list.registrable_domain("co.uk");
+        let result_4 = list.registrable_domain("tracker.example.test");
+
+        // assert
+        assert_eq!(result_0, Some("example.co.uk"));
+        assert_eq!(result_1, Some("ads.example.ck"));
+        assert_eq!(result_2, Some("www.ck"));
+        assert_eq!(result_3, None);
+        assert_eq!(result_4, Some("example.test"));
+    }
+
+    #[test]
+    fn count_by_registrable_domain_orders_largest_first() {
+        // arrange
+        let list = PublicSuffixList::bundled();
+        let hosts: Vec<Host> = [
+            "a.example.com",
+            "b.example.com",
+            "example.co.uk",
+            "ads.example.co.uk",
+            "c.example.com",
+        ]
+        .into_iter()
+        .map(|val| Host::parse(val).unwrap())
+        .collect();
+
+        // act
+        let result = list.count_by_registrable_domain(&hosts);
+
+        // assert
+        assert_eq!(
+            result,
+            vec![
+                (String::from("example.com"), 3),
+                (String::from("example.co.uk"), 2)
+            ]
+        );
+    }
+}
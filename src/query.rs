@@ -0,0 +1,447 @@
+use crate::{
+    add_source_id,
+    fetch::{AppError, Client as FetchClient, Provenance},
+    file_system::{get_custom_allowed_names, get_custom_blocked_patterns, Config},
+    keep_agreed_hosts,
+    parse::line_hostname,
+    pattern::NamePattern,
+    reject_invalid_names, remove_public_suffixes, sources_from_blocklists,
+    validate::RejectReason,
+    Source, SourceType, ALLOWED_NAMES_PATH, BLOCKED_NAMES_PATH, CUSTOM_SOURCE_NAME,
+};
+use ahash::RandomState;
+use log::{info, warn};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use url::Host;
+
+/// Source bodies saved by earlier queries, so repeated lookups do not refetch every source.
+pub struct SourceCache {
+    directory: PathBuf,
+    max_age: Duration,
+}
+
+impl SourceCache {
+    pub fn new<P: AsRef<Path>>(directory: P, max_age: Duration) -> Self {
+        SourceCache {
+            directory: directory.as_ref().to_path_buf(),
+            max_age,
+        }
+    }
+
+    /// Cache file for `url`, named by the URL's SHA-256 hash, so distinct URLs never share a file.
+    fn body_path(&self, url: &str) -> PathBuf {
+        self.directory
+            .join(format!("{:x}", Sha256::digest(url.as_bytes())))
+    }
+
+    fn fresh_body(&self, path: &Path) -> Option<String> {
+        let age = fs::metadata(path).ok()?.modified().ok()?.elapsed().ok()?;
+        if age > self.max_age {
+            return None;
+        }
+        fs::read_to_string(path).ok()
+    }
+
+    /// Returns the cached body for `url`, fetching and caching it if missing or stale.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the body is not cached and cannot be fetched.
+    pub async fn body(&self, client: &FetchClient, url: &str) -> Result<String, AppError> {
+        let path = self.body_path(url);
+        if let Some(value) = self.fresh_body(&path) {
+            info!("Using cached {url}");
+            return Ok(value);
+        }
+
+        let body = client.get_html_body(url).await?;
+        if let Err(error) =
+            fs::create_dir_all(&self.directory).and_then(|()| fs::write(&path, &body))
+        {
+            warn!("Unable to cache `{url}` at `{}`: {error}", path.display());
+        }
+        Ok(body)
+    }
+}
+
+/// How a source rule relates to the queried name.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MatchScope {
+    /// The rule names the queried name itself
+    Exact,
+
+    /// The rule names a parent domain of the queried name
+    ParentDomain,
+
+    /// A regex or glob line in `blocked-names.txt` matches the queried name
+    Pattern,
+}
+
+/// Whether a matching rule's name ends up in the blocklist, as a build decides.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RuleStatus {
+    Blocked,
+
+    /// Listed by too few sources to meet `min_sources`
+    TooFewSources,
+
+    /// Removed by an `allowed-names.txt` entry
+    Allowed,
+
+    /// Rejected as an invalid DNS name
+    Invalid(RejectReason),
+
+    /// Removed as a public suffix
+    PublicSuffix,
+}
+
+/// A source rule which blocks the queried name.
+#[derive(Debug, PartialEq)]
+pub struct RuleMatch {
+    /// Source URL, or custom names file path
+    pub source: String,
+
+    /// Line number within the source, starting at 1
+    pub line_number: usize,
+
+    /// Name, or pattern, listed by the source
+    pub rule: String,
+
+    pub scope: MatchScope,
+
+    pub status: RuleStatus,
+}
+
+/// Finds every line of a source body listing `name`, or one of its parent domains, returning each
+/// match with the listed host.
+fn find_matches(
+    name: &str,
+    source: &str,
+    source_type: SourceType,
+    body: &str,
+) -> Vec<(Host, RuleMatch)> {
+    body.lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let rule = Host::parse(line_hostname(source_type, line)?).ok()?;
+            let rule_name = rule.to_string();
+            let scope = if name == rule_name {
+                MatchScope::Exact
+            } else if name.ends_with(&format!(".{rule_name}")) {
+                MatchScope::ParentDomain
+            } else {
+                return None;
+            };
+            let rule_match = RuleMatch {
+                source: source.to_string(),
+                line_number: index + 1,
+                rule: rule_name,
+                scope,
+                status: RuleStatus::Blocked,
+            };
+            Some((rule, rule_match))
+        })
+        .collect()
+}
+
+/// Finds every regex and glob line of a custom names file matching `name`.
+fn find_pattern_matches(name: &str, source: &str, body: &str) -> Vec<RuleMatch> {
+    body.lines()
+        .enumerate()
+        .filter_map(|(index, line)| {
+            let pattern = NamePattern::parse(line)?.ok()?;
+            pattern.is_match(name).then(|| RuleMatch {
+                source: source.to_string(),
+                line_number: index + 1,
+                rule: pattern.to_source(),
+                scope: MatchScope::Pattern,
+                status: RuleStatus::Blocked,
+            })
+        })
+        .collect()
+}
+
+/// Records why each of `hosts` no longer in `provenance` was removed, unless already recorded.
+fn mark_removed(
+    removed: &mut HashMap<Host, RuleStatus, RandomState>,
+    hosts: &[Host],
+    provenance: &Provenance,
+    status: impl Fn(&Host) -> Option<RuleStatus>,
+) {
+    for host in hosts {
+        if provenance.contains_key(host) || removed.contains_key(host) {
+            continue;
+        }
+        if let Some(value) = status(host) {
+            removed.insert(host.clone(), value);
+        }
+    }
+}
+
+/// Applies the build's filtering to the hosts named by matching rules, returning why each host
+/// left out of the blocklist was dropped.  `provenance` holds the hosts listed by sources, and
+/// `custom_names` those listed in `blocked-names.txt`.
+fn removed_hosts(
+    config: &Config,
+    sources: &[Source],
+    mut provenance: Provenance,
+    custom_names: Vec<Host>,
+    patterns: &[NamePattern],
+) -> HashMap<Host, RuleStatus, RandomState> {
+    let mut result: HashMap<Host, RuleStatus, RandomState> = HashMap::default();
+
+    let listed_hosts: Vec<Host> = provenance.keys().cloned().collect();
+    keep_agreed_hosts(
+        &mut provenance,
+        sources,
+        &config.blocklists,
+        patterns,
+        sources.len(),
+    );
+    mark_removed(&mut result, &listed_hosts, &provenance, |_| {
+        Some(RuleStatus::TooFewSources)
+    });
+
+    let mut hosts = listed_hosts;
+    for custom_name in custom_names {
+        add_source_id(
+            provenance.entry(custom_name.clone()).or_default(),
+            sources.len(),
+        );
+        hosts.push(custom_name);
+    }
+    for allowed_name in get_custom_allowed_names(ALLOWED_NAMES_PATH) {
+        provenance.remove(&allowed_name);
+    }
+    mark_removed(&mut result, &hosts, &provenance, |_| {
+        Some(RuleStatus::Allowed)
+    });
+
+    let mut source_names: Vec<String> = sources.iter().map(|val| val.name.clone()).collect();
+    source_names.push(String::from(CUSTOM_SOURCE_NAME));
+    let rejected =
+        reject_invalid_names(&mut provenance, &source_names, config.validation.strictness);
+    mark_removed(&mut result, &hosts, &provenance, |host| {
+        let name = host.to_string();
+        rejected
+            .iter()
+            .find(|val| val.name == name)
+            .map(|val| RuleStatus::Invalid(val.reason))
+    });
+
+    remove_public_suffixes(&mut provenance, &source_names, &config.public_suffixes);
+    mark_removed(&mut result, &hosts, &provenance, |_| {
+        Some(RuleStatus::PublicSuffix)
+    });
+    result
+}
+
+/// Lists every configured source rule, and custom blocked name or pattern, which blocks `name`,
+/// with whether the build keeps each rule's name in the blocklist.  Hosts kept only by the
+/// retention grace period are reported as dropped.
+///
+/// # Errors
+///
+/// Returns an error if a source is not cached and cannot be fetched.
+pub async fn query(config: &Config, name: &str) -> Result<Vec<RuleMatch>, AppError> {
+    let name = name.trim_end_matches('.').to_lowercase();
+    let cache = SourceCache::new(&config.query.cache_directory, config.query.max_cache_age);
+    let fetch_client = FetchClient::new(&config.http, &config.blocklists)?;
+    let sources = sources_from_blocklists(&config.blocklists);
+
+    let mut matches: Vec<(Host, RuleMatch)> = Vec::new();
+    let mut provenance: Provenance = HashMap::default();
+    for (source_id, source) in sources.iter().enumerate() {
+        if matches!(
+            source.source_type,
+            SourceType::IpList | SourceType::ClientIpList
+        ) {
+            continue;
+        }
+        let body = cache.body(&fetch_client, source.url).await?;
+        for (host, rule_match) in find_matches(&name, source.url, source.source_type, &body) {
+            add_source_id(provenance.entry(host.clone()).or_default(), source_id);
+            matches.push((host, rule_match));
+        }
+    }
+    let mut custom_names = Vec::new();
+    let mut pattern_matches = Vec::new();
+    if let Ok(value) = fs::read_to_string(BLOCKED_NAMES_PATH) {
+        for (host, rule_match) in
+            find_matches(&name, BLOCKED_NAMES_PATH, SourceType::DomainList, &value)
+        {
+            custom_names.push(host.clone());
+            matches.push((host, rule_match));
+        }
+        pattern_matches = find_pattern_matches(&name, BLOCKED_NAMES_PATH, &value);
+    }
+
+    let patterns = get_custom_blocked_patterns(BLOCKED_NAMES_PATH);
+    let removed = removed_hosts(config, &sources, provenance, custom_names, &patterns);
+    let mut result: Vec<RuleMatch> = matches
+        .into_iter()
+        .map(|(host, mut rule_match)| {
+            if let Some(value) = removed.get(&host) {
+                rule_match.status = *value;
+            }
+            rule_match
+        })
+        .collect();
+    result.extend(pattern_matches);
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        find_matches, find_pattern_matches, removed_hosts, MatchScope, RuleMatch, RuleStatus,
+        SourceCache,
+    };
+    use crate::{
+        fetch::Provenance, file_system::Config, pattern::NamePattern, sources_from_blocklists,
+        validate::RejectReason, SourceType,
+    };
+    use std::{collections::HashMap, fs, time::Duration};
+    use url::Host;
+
+    #[test]
+    fn find_matches_reports_exact_and_parent_domain_rules() {
+        // arrange
+        let body = "# comment\n0.0.0.0 ads.example.com\n0.0.0.0 example.com\n0.0.0.0 badexample.com\n0.0.0.0 other.example.com\n";
+
+        // act
+        let result = find_matches(
+            "ads.example.com",
+            "https://example.net/hosts",
+            SourceType::HostsFile,
+            body,
+        );
+
+        // assert
+        assert_eq!(
+            result,
+            vec![
+                (
+                    Host::parse("ads.example.com").unwrap(),
+                    RuleMatch {
+                        source: String::from("https://example.net/hosts"),
+                        line_number: 2,
+                        rule: String::from("ads.example.com"),
+                        scope: MatchScope::Exact,
+                        status: RuleStatus::Blocked,
+                    }
+                ),
+                (
+                    Host::parse("example.com").unwrap(),
+                    RuleMatch {
+                        source: String::from("https://example.net/hosts"),
+                        line_number: 3,
+                        rule: String::from("example.com"),
+                        scope: MatchScope::ParentDomain,
+                        status: RuleStatus::Blocked,
+                    }
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn find_pattern_matches_reports_matching_regex_and_glob_lines() {
+        // arrange
+        let body = "example.com\n/^ads?\\d*\\./\n*tracker*.example.com\n*.example.net\n";
+
+        // act
+        let result = find_pattern_matches("ads1.example.com", "blocked-names.txt", body);
+
+        // assert
+        assert_eq!(
+            result,
+            vec![RuleMatch {
+                source: String::from("blocked-names.txt"),
+                line_number: 2,
+                rule: String::from("/^ads?\\d*\\./"),
+                scope: MatchScope::Pattern,
+                status: RuleStatus::Blocked,
+            }]
+        );
+    }
+
+    #[test]
+    fn removed_hosts_applies_build_filtering() {
+        // arrange
+        let config: Config = toml::from_str(
+            r#"
+            [blocklists]
+            domain_blocklist_urls = ["https://example.net/0.txt", "https://example.net/1.txt"]
+            min_sources = 2
+
+            [validation]
+            strictness = "strict"
+            "#,
+        )
+        .unwrap();
+        let sources = sources_from_blocklists(&config.blocklists);
+        let host = |name: &str| Host::parse(name).unwrap();
+        let provenance: Provenance = [
+            (host("ads.example.com"), vec![0]),
+            (host("tracker.example.com"), vec![0]),
+            (host("example.com"), vec![0, 1]),
+            (host("co.uk"), vec![0, 1]),
+        ]
+        .into_iter()
+        .collect();
+        let patterns = vec![NamePattern::parse("tracker.*").unwrap().unwrap()];
+
+        // act
+        let result = removed_hosts(
+            &config,
+            &sources,
+            provenance,
+            vec![host("bad_name.example.com")],
+            &patterns,
+        );
+
+        // assert
+        let expected: HashMap<Host, RuleStatus> = [
+            (host("ads.example.com"), RuleStatus::TooFewSources),
+            (
+                host("bad_name.example.com"),
+                RuleStatus::Invalid(RejectReason::Underscore),
+            ),
+            (host("co.uk"), RuleStatus::PublicSuffix),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(result.into_iter().collect::<HashMap<_, _>>(), expected);
+    }
+
+    #[test]
+    fn source_cache_uses_fresh_bodies_only() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let fresh_cache = SourceCache::new(directory.path(), Duration::from_secs(60));
+        let stale_cache = SourceCache::new(directory.path(), Duration::ZERO);
+        let path = fresh_cache.body_path("https://example.net/hosts?format=hosts");
+        fs::write(&path, "example.com\n").unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+
+        // act
+        let fresh_result = fresh_cache.fresh_body(&path);
+        let stale_result = stale_cache.fresh_body(&path);
+
+        // assert
+        assert_ne!(
+            path,
+            fresh_cache.body_path("https://example.net/hosts_format=hosts")
+        );
+        assert_eq!(path.file_name().unwrap().len(), 64);
+        assert_eq!(fresh_result, Some(String::from("example.com\n")));
+        assert_eq!(stale_result, None);
+    }
+}
@@ -0,0 +1,145 @@
+use crate::dns::{answer_addresses, parse_message, query_message, TYPE_A, TYPE_AAAA};
+use futures::StreamExt;
+use log::debug;
+use std::{
+    collections::BTreeSet,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+use tokio::{net::UdpSocket, time::timeout};
+use url::Host;
+
+/// Stub resolver, looking up the addresses blocked domains currently resolve to, so firewalls can
+/// block connections made without DNS.
+pub struct Resolver {
+    server: SocketAddr,
+    timeout: Duration,
+    concurrency: usize,
+}
+
+impl Resolver {
+    #[must_use]
+    pub fn new(server: SocketAddr, timeout: Duration, concurrency: usize) -> Self {
+        Resolver {
+            server,
+            timeout,
+            concurrency: concurrency.max(1),
+        }
+    }
+
+    /// Sends a single query over UDP, returning the addresses in the answer.
+    async fn lookup(&self, name: &str, record_type: u16) -> io::Result<Vec<IpAddr>> {
+        let local_address: SocketAddr = if self.server.is_ipv4() {
+            (Ipv4Addr::UNSPECIFIED, 0).into()
+        } else {
+            (Ipv6Addr::UNSPECIFIED, 0).into()
+        };
+        let socket = UdpSocket::bind(local_address).await?;
+        socket.connect(self.server).await?;
+        let id: u16 = rand::random();
+        socket.send(&query_message(id, name, record_type)).await?;
+
+        let mut buffer = [0u8; 4096];
+        loop {
+            let length = timeout(self.timeout, socket.recv(&mut buffer))
+                .await
+                .map_err(|_| io::Error::new(io::ErrorKind::TimedOut, "resolver timed out"))??;
+            // ignore stray responses to earlier queries
+            match parse_message(&buffer[..length]) {
+                Some(value) if value.header.id == id && value.header.is_response() => {
+                    return Ok(answer_addresses(&value));
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Addresses for the `A` and `AAAA` records of `name`.  Lookup failures are logged, and give
+    /// no addresses.
+    async fn resolve(&self, name: &str) -> Vec<IpAddr> {
+        let mut result = Vec::new();
+        for record_type in [TYPE_A, TYPE_AAAA] {
+            match self.lookup(name, record_type).await {
+                Ok(value) => result.extend(value),
+                Err(error) => debug!("Unable to resolve `{name}`: {error}"),
+            }
+        }
+        result
+    }
+
+    /// Resolves every blocked domain, a few at a time, returning the distinct addresses found.
+    pub async fn resolve_all(&self, hosts: &[Host]) -> BTreeSet<IpAddr> {
+        let names = hosts.iter().filter_map(|host| match host {
+            Host::Domain(value) => Some(value.as_str()),
+            Host::Ipv4(_) | Host::Ipv6(_) => None,
+        });
+        futures::stream::iter(names)
+            .map(|name| self.resolve(name))
+            .buffer_unordered(self.concurrency)
+            .collect::<Vec<Vec<IpAddr>>>()
+            .await
+            .into_iter()
+            .flatten()
+            .filter(|val| !val.is_unspecified() && !val.is_loopback())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Resolver;
+    use crate::dns::{blocked_response, empty_response, parse_query, RCODE_NXDOMAIN};
+    use std::{
+        net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+        time::Duration,
+    };
+    use tokio::net::UdpSocket;
+    use url::Host;
+
+    /// Resolver stub, answering for `ads.example.com` and returning `NXDOMAIN` for other names.
+    async fn stub_resolver() -> SocketAddr {
+        let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap(); // DevSkim: ignore DS162092 - use of local host IP is in test
+        let address = socket.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut buffer = [0u8; 512];
+            while let Ok((length, peer)) = socket.recv_from(&mut buffer).await {
+                let query = parse_query(&buffer[..length]).unwrap();
+                let response = if query.question.name == "ads.example.com" {
+                    blocked_response(
+                        &query,
+                        Some(Ipv4Addr::new(192, 0, 2, 1)),
+                        Some(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1)),
+                        60,
+                    )
+                } else {
+                    empty_response(&query, RCODE_NXDOMAIN)
+                };
+                socket.send_to(&response, peer).await.unwrap();
+            }
+        });
+        address
+    }
+
+    #[tokio::test]
+    async fn resolve_all_collects_a_and_aaaa_addresses() {
+        // arrange
+        let resolver = Resolver::new(stub_resolver().await, Duration::from_secs(2), 4);
+        let hosts = [
+            Host::parse("ads.example.com").unwrap(),
+            Host::parse("missing.example.com").unwrap(),
+        ];
+
+        // act
+        let result = resolver.resolve_all(&hosts).await;
+
+        // assert
+        assert_eq!(
+            result.into_iter().collect::<Vec<IpAddr>>(),
+            vec![
+                IpAddr::from(Ipv4Addr::new(192, 0, 2, 1)),
+                IpAddr::from(Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1))
+            ]
+        );
+    }
+}
@@ -0,0 +1,203 @@
+use crate::fetch::Provenance;
+use ahash::RandomState;
+use log::{info, warn};
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    fs, io,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use url::Host;
+
+/// When a host was last listed by a source, and the sources which listed it then.
+#[derive(Clone, Debug, PartialEq)]
+struct StateEntry {
+    last_seen: u64,
+    sources: Vec<String>,
+}
+
+/// Hosts listed upstream on recent builds, read from and written to a tab-separated state file
+/// with one host, last seen Unix time and comma-separated source names per line.
+#[derive(Debug, Default)]
+pub struct RetentionState {
+    entries: HashMap<Host, StateEntry, RandomState>,
+}
+
+fn parse_host(name: &str) -> Host {
+    Host::parse(name).unwrap_or_else(|_| Host::Domain(name.to_string()))
+}
+
+impl RetentionState {
+    /// Reads the state file.  A missing or unreadable file gives an empty state, so no hosts are
+    /// retained.
+    pub fn load<P: AsRef<Path>>(path: P) -> Self {
+        let content = match fs::read_to_string(&path) {
+            Ok(value) => value,
+            Err(error) => {
+                if error.kind() != io::ErrorKind::NotFound {
+                    warn!(
+                        "Unable to read retention state `{}`: {error}",
+                        path.as_ref().display()
+                    );
+                }
+                return RetentionState::default();
+            }
+        };
+        let entries = content
+            .lines()
+            .filter_map(|line| {
+                let mut fields = line.split('\t');
+                let host = parse_host(fields.next()?);
+                let last_seen = fields.next()?.parse().ok()?;
+                let sources = fields
+                    .next()
+                    .unwrap_or_default()
+                    .split(',')
+                    .filter(|val| !val.is_empty())
+                    .map(String::from)
+                    .collect();
+                Some((host, StateEntry { last_seen, sources }))
+            })
+            .collect();
+        RetentionState { entries }
+    }
+
+    /// Writes the state file, hosts sorted.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be written.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut hosts: Vec<&Host> = self.entries.keys().collect();
+        hosts.sort();
+        let content = hosts.into_iter().fold(String::new(), |mut acc, val| {
+            let entry = &self.entries[val];
+            let _ = writeln!(
+                acc,
+                "{val}\t{}\t{}",
+                entry.last_seen,
+                entry.sources.join(",")
+            );
+            acc
+        });
+        fs::write(path, content)
+    }
+
+    /// Marks hosts in `provenance` as seen at `now`, with the sources in `source_names` listing
+    /// them, then adds back hosts missing from it which were last seen within `grace_period`,
+    /// attributed to `retained_source_id` alone.  Hosts missing for longer are forgotten.  Returns
+    /// the number of hosts added back.
+    pub fn retain_recent(
+        &mut self,
+        provenance: &mut Provenance,
+        source_names: &[String],
+        retained_source_id: usize,
+        grace_period: Duration,
+        now: SystemTime,
+    ) -> usize {
+        let now = now
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |val| val.as_secs());
+        for (host, source_ids) in provenance.iter() {
+            self.entries.insert(
+                host.clone(),
+                StateEntry {
+                    last_seen: now,
+                    sources: source_ids
+                        .iter()
+                        .filter_map(|id| source_names.get(*id).cloned())
+                        .collect(),
+                },
+            );
+        }
+
+        let cutoff = now.saturating_sub(grace_period.as_secs());
+        self.entries.retain(|_, entry| entry.last_seen >= cutoff);
+        let mut result = 0;
+        for host in self.entries.keys() {
+            if provenance.contains_key(host) {
+                continue;
+            }
+            provenance.insert(host.clone(), vec![retained_source_id]);
+            result += 1;
+        }
+        result
+    }
+}
+
+/// Keeps hosts which dropped out of every source within the grace period, using the state file
+/// to remember when each host was last listed.  Kept hosts are attributed to `retained_source_id`.
+pub fn retain_recent_hosts(
+    provenance: &mut Provenance,
+    source_names: &[String],
+    retained_source_id: usize,
+    grace_period: Duration,
+    state_file: &Path,
+) {
+    let mut state = RetentionState::load(state_file);
+    let retained_count = state.retain_recent(
+        provenance,
+        source_names,
+        retained_source_id,
+        grace_period,
+        SystemTime::now(),
+    );
+    if retained_count > 0 {
+        info!("Kept {retained_count} hosts no longer listed upstream, within the grace period");
+    }
+    if let Err(error) = state.save(state_file) {
+        warn!(
+            "Unable to write retention state `{}`: {error}",
+            state_file.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RetentionState;
+    use crate::fetch::Provenance;
+    use std::time::{Duration, UNIX_EPOCH};
+    use url::Host;
+
+    #[test]
+    fn retain_recent_keeps_missing_hosts_for_grace_period() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("retention-state.tsv");
+        let source_names = [String::from("yoyo"), String::from("firebog")];
+        let grace_period = Duration::from_secs(72 * 60 * 60);
+        let ads_host = Host::parse("ads.example.com").unwrap();
+        let hour = Duration::from_secs(60 * 60);
+        let mut provenance: Provenance = [(ads_host.clone(), vec![1])].into_iter().collect();
+        let mut state = RetentionState::load(&path);
+        state.retain_recent(&mut provenance, &source_names, 3, grace_period, UNIX_EPOCH);
+        state.save(&path).unwrap();
+
+        // act
+        let mut state = RetentionState::load(&path);
+        let mut provenance_0 = Provenance::default();
+        let result_0 = state.retain_recent(
+            &mut provenance_0,
+            &source_names,
+            3,
+            grace_period,
+            UNIX_EPOCH + 71 * hour,
+        );
+        let mut provenance_1 = Provenance::default();
+        let result_1 = state.retain_recent(
+            &mut provenance_1,
+            &source_names,
+            3,
+            grace_period,
+            UNIX_EPOCH + 73 * hour,
+        );
+
+        // assert
+        assert_eq!(result_0, 1);
+        assert_eq!(provenance_0.get(&ads_host), Some(&vec![3]));
+        assert_eq!(result_1, 0);
+        assert!(provenance_1.is_empty());
+    }
+}
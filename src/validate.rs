@@ -0,0 +1,192 @@
+use serde::Deserialize;
+use std::{
+    fmt::{self, Write},
+    fs, io,
+    path::Path,
+};
+use url::Host;
+
+/// Longest name allowed in DNS, in its dotted presentation form, without a trailing dot
+const MAX_NAME_LENGTH: usize = 253;
+
+const MAX_LABEL_LENGTH: usize = 63;
+
+/// How closely blocked names must follow DNS hostname rules.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, Ord, PartialEq, PartialOrd)]
+#[serde(rename_all = "lowercase")]
+pub enum Strictness {
+    /// Reject IP address literals and names too long for DNS
+    Lenient,
+
+    /// Also reject empty labels, labels starting or ending with a hyphen and all-numeric
+    /// top-level domains
+    #[default]
+    Standard,
+
+    /// Also reject underscores, which hostnames may not contain (RFC 952 and RFC 1123)
+    Strict,
+}
+
+/// Why a blocked name was rejected.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RejectReason {
+    IpAddress,
+    NameTooLong,
+    LabelTooLong,
+    EmptyLabel,
+    HyphenPosition,
+    NumericTopLevelDomain,
+    Underscore,
+}
+
+impl fmt::Display for RejectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let description = match self {
+            RejectReason::IpAddress => "IP address literal",
+            RejectReason::NameTooLong => "name longer than 253 characters",
+            RejectReason::LabelTooLong => "label longer than 63 characters",
+            RejectReason::EmptyLabel => "empty label",
+            RejectReason::HyphenPosition => "label starts or ends with a hyphen",
+            RejectReason::NumericTopLevelDomain => "all-numeric top-level domain",
+            RejectReason::Underscore => "underscore in name",
+        };
+        f.write_str(description)
+    }
+}
+
+/// Checks `host` is a valid DNS name, at the given strictness.
+///
+/// # Errors
+///
+/// Returns the first rule `host` breaks.
+pub fn validate(host: &Host, strictness: Strictness) -> Result<(), RejectReason> {
+    let name = match host {
+        Host::Domain(value) => value.trim_end_matches('.'),
+        Host::Ipv4(_) | Host::Ipv6(_) => return Err(RejectReason::IpAddress),
+    };
+    if name.len() > MAX_NAME_LENGTH {
+        return Err(RejectReason::NameTooLong);
+    }
+    let labels: Vec<&str> = name.split('.').collect();
+    if labels.iter().any(|val| val.len() > MAX_LABEL_LENGTH) {
+        return Err(RejectReason::LabelTooLong);
+    }
+    if strictness == Strictness::Lenient {
+        return Ok(());
+    }
+
+    if labels.iter().any(|val| val.is_empty()) {
+        return Err(RejectReason::EmptyLabel);
+    }
+    if labels
+        .iter()
+        .any(|val| val.starts_with('-') || val.ends_with('-'))
+    {
+        return Err(RejectReason::HyphenPosition);
+    }
+    if labels
+        .last()
+        .is_some_and(|val| val.bytes().all(|byte| byte.is_ascii_digit()))
+    {
+        return Err(RejectReason::NumericTopLevelDomain);
+    }
+    if strictness == Strictness::Strict && name.contains('_') {
+        return Err(RejectReason::Underscore);
+    }
+    Ok(())
+}
+
+/// A name dropped from the blocklist by validation.
+#[derive(Debug, PartialEq)]
+pub struct RejectedEntry {
+    pub name: String,
+    pub reason: RejectReason,
+
+    /// Names of the sources listing the entry
+    pub sources: Vec<String>,
+}
+
+/// Writes rejected entries as tab-separated name, reason and sources, one entry per line.
+///
+/// # Errors
+///
+/// Returns an error if the report cannot be written.
+pub fn write_rejected_report<P: AsRef<Path>>(
+    path: P,
+    rejected: &[RejectedEntry],
+) -> io::Result<()> {
+    let content = rejected.iter().fold(String::new(), |mut acc, val| {
+        let _ = writeln!(
+            acc,
+            "{}\t{}\t{}",
+            val.name,
+            val.reason,
+            val.sources.join(", ")
+        );
+        acc
+    });
+    fs::write(path, content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate, RejectReason, Strictness};
+    use url::Host;
+
+    #[test]
+    fn validate_rejects_invalid_names_by_strictness() {
+        // arrange
+        let long_name = format!("{}.example.com", "a.".repeat(125));
+        let hosts = [
+            Host::parse("0.0.0.0").unwrap(),
+            Host::Domain(long_name),
+            Host::Domain(String::from("-ads.example.com")),
+            Host::Domain(String::from("ads-.example.com")),
+            Host::Domain(String::from("ads.example.123")),
+            Host::Domain(String::from("_ads.example.com")),
+            Host::Domain(String::from("ads-1.example.com")),
+        ];
+
+        // act
+        let lenient: Vec<Result<(), RejectReason>> = hosts
+            .iter()
+            .map(|val| validate(val, Strictness::Lenient))
+            .collect();
+        let standard: Vec<Result<(), RejectReason>> = hosts
+            .iter()
+            .map(|val| validate(val, Strictness::Standard))
+            .collect();
+        let strict: Vec<Result<(), RejectReason>> = hosts
+            .iter()
+            .map(|val| validate(val, Strictness::Strict))
+            .collect();
+
+        // assert
+        assert_eq!(
+            lenient,
+            vec![
+                Err(RejectReason::IpAddress),
+                Err(RejectReason::NameTooLong),
+                Ok(()),
+                Ok(()),
+                Ok(()),
+                Ok(()),
+                Ok(())
+            ]
+        );
+        assert_eq!(
+            standard,
+            vec![
+                Err(RejectReason::IpAddress),
+                Err(RejectReason::NameTooLong),
+                Err(RejectReason::HyphenPosition),
+                Err(RejectReason::HyphenPosition),
+                Err(RejectReason::NumericTopLevelDomain),
+                Ok(()),
+                Ok(())
+            ]
+        );
+        assert_eq!(strict[5], Err(RejectReason::Underscore));
+        assert_eq!(strict[6], Ok(()));
+    }
+}
@@ -0,0 +1,396 @@
+use crate::{
+    dns::{Record, RecordData, Soa},
+    file_system::replace_file,
+    output::rpz_owner_names,
+    Blocklist,
+};
+use log::{info, warn};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+const SNAPSHOT_EXTENSION: &str = "zone";
+
+/// A published version of the response policy zone.
+#[derive(Debug, PartialEq)]
+pub struct Zone {
+    pub origin: String,
+    pub serial: u32,
+    pub ttl: u32,
+
+    /// Owner names of the zone's `CNAME .` records, relative to the origin and sorted
+    pub owners: Vec<String>,
+}
+
+impl Zone {
+    /// The `SOA` record, mirroring the one in the `blocklist.rpz` template.
+    #[must_use]
+    pub fn soa(&self) -> Record {
+        Record {
+            name: self.origin.clone(),
+            ttl: self.ttl,
+            data: RecordData::Soa(Soa {
+                mname: String::from("localhost"),
+                rname: String::from("root.localhost"),
+                serial: self.serial,
+                refresh: 10_800,
+                retry: 3_600,
+                expire: 604_800,
+                minimum: 3_600,
+            }),
+        }
+    }
+
+    fn ns(&self) -> Record {
+        Record {
+            name: self.origin.clone(),
+            ttl: self.ttl,
+            data: RecordData::Ns(String::from("localhost")),
+        }
+    }
+
+    /// Record blocking the name `owner`, relative to the origin.
+    fn owner_record(&self, owner: &str) -> Record {
+        Record {
+            name: format!("{owner}.{}", self.origin),
+            ttl: self.ttl,
+            data: RecordData::Cname(String::new()),
+        }
+    }
+
+    /// Full zone, in `AXFR` order: `SOA`, all other records, then `SOA` again.
+    #[must_use]
+    pub fn axfr_records(&self) -> Vec<Record> {
+        let mut result = Vec::with_capacity(self.owners.len() + 3);
+        result.push(self.soa());
+        result.push(self.ns());
+        for owner in &self.owners {
+            result.push(self.owner_record(owner));
+        }
+        result.push(self.soa());
+        result
+    }
+
+    /// Condensed incremental transfer from `previous` to this zone (RFC 1995 section 4).
+    #[must_use]
+    pub fn ixfr_records(&self, previous: &Zone) -> Vec<Record> {
+        let mut deleted: Vec<&String> = Vec::new();
+        let mut added: Vec<&String> = Vec::new();
+        let (mut old, mut new) = (
+            previous.owners.iter().peekable(),
+            self.owners.iter().peekable(),
+        );
+        loop {
+            match (old.peek(), new.peek()) {
+                (Some(old_owner), Some(new_owner)) => match old_owner.cmp(new_owner) {
+                    std::cmp::Ordering::Less => deleted.extend(old.next()),
+                    std::cmp::Ordering::Greater => added.extend(new.next()),
+                    std::cmp::Ordering::Equal => {
+                        old.next();
+                        new.next();
+                    }
+                },
+                (Some(_), None) => deleted.extend(old.next()),
+                (None, Some(_)) => added.extend(new.next()),
+                (None, None) => break,
+            }
+        }
+
+        let current_soa = self.soa();
+        let mut result = Vec::with_capacity(deleted.len() + added.len() + 4);
+        result.push(current_soa.clone());
+        result.push(previous.soa());
+        for owner in deleted {
+            result.push(self.owner_record(owner));
+        }
+        result.push(current_soa.clone());
+        for owner in added {
+            result.push(self.owner_record(owner));
+        }
+        result.push(current_soa);
+        result
+    }
+}
+
+/// Snapshots of each published zone version, one file per serial, so incremental transfers can be
+/// computed between any retained serial and the latest.
+pub struct SnapshotStore {
+    directory: PathBuf,
+    keep: usize,
+}
+
+fn unix_time_serial() -> u32 {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|val| val.as_secs())
+        .unwrap_or_default();
+    u32::try_from(seconds).unwrap_or(u32::MAX)
+}
+
+impl SnapshotStore {
+    pub fn new<P: AsRef<Path>>(directory: P, keep: usize) -> Self {
+        SnapshotStore {
+            directory: directory.as_ref().to_path_buf(),
+            keep: keep.max(1),
+        }
+    }
+
+    fn snapshot_path(&self, serial: u32) -> PathBuf {
+        self.directory
+            .join(format!("{serial}.{SNAPSHOT_EXTENSION}"))
+    }
+
+    /// Retained serials, in ascending order.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot directory cannot be read.
+    pub fn serials(&self) -> io::Result<Vec<u32>> {
+        let mut result: Vec<u32> = match fs::read_dir(&self.directory) {
+            Ok(value) => value
+                .filter_map(Result::ok)
+                .filter_map(|val| {
+                    let path = val.path();
+                    if path.extension()? != SNAPSHOT_EXTENSION {
+                        return None;
+                    }
+                    path.file_stem()?.to_str()?.parse().ok()
+                })
+                .collect(),
+            Err(error) if error.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(error) => return Err(error),
+        };
+        result.sort_unstable();
+        Ok(result)
+    }
+
+    /// Most recently published serial.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot directory cannot be read.
+    pub fn latest_serial(&self) -> io::Result<Option<u32>> {
+        Ok(self.serials()?.last().copied())
+    }
+
+    /// Loads the zone published with `serial`, if its snapshot is still kept.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot exists but cannot be read.
+    pub fn load(&self, origin: &str, ttl: u32, serial: u32) -> io::Result<Option<Zone>> {
+        let content = match fs::read_to_string(self.snapshot_path(serial)) {
+            Ok(value) => value,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(error) => return Err(error),
+        };
+        Ok(Some(Zone {
+            origin: origin.to_string(),
+            serial,
+            ttl,
+            owners: content.lines().map(String::from).collect(),
+        }))
+    }
+
+    /// Loads the most recently published zone.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the snapshot directory or snapshot cannot be read.
+    pub fn latest(&self, origin: &str, ttl: u32) -> io::Result<Option<Zone>> {
+        match self.latest_serial()? {
+            Some(value) => self.load(origin, ttl, value),
+            None => Ok(None),
+        }
+    }
+
+    /// Stores a new snapshot when the blocklist's RPZ records, as written by the `rpz` output
+    /// format, differ from the latest one, pruning the oldest snapshots beyond the number kept.
+    /// Returns the new serial, if a snapshot was stored.  Serials follow the current Unix time, and
+    /// always increase.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if snapshots cannot be read or written.
+    pub fn publish(&self, blocklist: &Blocklist) -> io::Result<Option<u32>> {
+        let mut owners = rpz_owner_names(blocklist);
+        owners.sort_unstable();
+        owners.dedup();
+
+        let serials = self.serials()?;
+        let latest_serial = serials.last().copied();
+        if let Some(value) = latest_serial {
+            if let Some(latest) = self.load("", 0, value)? {
+                if latest.owners == owners {
+                    info!("Zone unchanged at serial {value}");
+                    return Ok(None);
+                }
+            }
+        }
+
+        let serial = match latest_serial {
+            Some(value) => unix_time_serial().max(value.saturating_add(1)),
+            None => unix_time_serial(),
+        };
+        fs::create_dir_all(&self.directory)?;
+        let mut content = owners.join("\n");
+        content.push('\n');
+        // the zone server may read the latest snapshot at any time
+        replace_file(self.snapshot_path(serial), content.as_bytes())?;
+        info!("Published zone serial {serial}");
+
+        let prune_count = (serials.len() + 1).saturating_sub(self.keep);
+        for old_serial in serials.iter().take(prune_count) {
+            if let Err(error) = fs::remove_file(self.snapshot_path(*old_serial)) {
+                warn!("Unable to remove zone snapshot for serial {old_serial}: {error}");
+            }
+        }
+        Ok(Some(serial))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SnapshotStore, Zone};
+    use crate::{
+        dns::{Record, RecordData},
+        pattern::NamePattern,
+        Blocklist,
+    };
+    use url::Host;
+
+    fn zone(serial: u32, owners: &[&str]) -> Zone {
+        Zone {
+            origin: String::from("rpz.example"),
+            serial,
+            ttl: 60,
+            owners: owners.iter().map(|val| String::from(*val)).collect(),
+        }
+    }
+
+    fn blocklist(names: &[&str]) -> Blocklist {
+        Blocklist {
+            hosts: names.iter().map(|val| Host::parse(val).unwrap()).collect(),
+            ..Blocklist::default()
+        }
+    }
+
+    fn cname_owner(record: &Record) -> Option<&str> {
+        match record.data {
+            RecordData::Cname(_) => Some(record.name.as_str()),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn axfr_records_wrap_zone_in_soa_records() {
+        // arrange
+        let zone = zone(3, &["*.ads.example.com", "ads.example.com"]);
+
+        // act
+        let result = zone.axfr_records();
+
+        // assert
+        assert_eq!(result.len(), 5);
+        assert_eq!(result[0], zone.soa());
+        assert_eq!(
+            cname_owner(&result[2]),
+            Some("*.ads.example.com.rpz.example")
+        );
+        assert_eq!(cname_owner(&result[3]), Some("ads.example.com.rpz.example"));
+        assert_eq!(result[4], zone.soa());
+    }
+
+    #[test]
+    fn ixfr_records_lists_deleted_then_added_records() {
+        // arrange
+        let previous = zone(1, &["a.example", "b.example", "c.example"]);
+        let current = zone(2, &["b.example", "c.example", "d.example", "e.example"]);
+
+        // act
+        let result = current.ixfr_records(&previous);
+
+        // assert
+        let owners: Vec<Option<&str>> = result.iter().map(cname_owner).collect();
+        assert_eq!(
+            owners,
+            vec![
+                None,
+                None,
+                Some("a.example.rpz.example"),
+                None,
+                Some("d.example.rpz.example"),
+                Some("e.example.rpz.example"),
+                None
+            ]
+        );
+        assert_eq!(result[0], current.soa());
+        assert_eq!(result[1], previous.soa());
+        assert_eq!(result[3], current.soa());
+        assert_eq!(result[6], current.soa());
+    }
+
+    #[test]
+    fn publish_stores_changed_snapshots_with_increasing_serials() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let store = SnapshotStore::new(directory.path(), 2);
+        let blocklist_0 = blocklist(&["b.example"]);
+        let blocklist_1 = blocklist(&["a.example", "b.example"]);
+        let blocklist_2 = blocklist(&["c.example"]);
+
+        // act
+        let serial_0 = store.publish(&blocklist_0).unwrap().unwrap();
+        let unchanged = store.publish(&blocklist_0).unwrap();
+        let serial_1 = store.publish(&blocklist_1).unwrap().unwrap();
+        let serial_2 = store.publish(&blocklist_2).unwrap().unwrap();
+
+        // assert
+        assert_eq!(unchanged, None);
+        assert!(serial_0 < serial_1 && serial_1 < serial_2);
+        assert_eq!(store.serials().unwrap(), vec![serial_1, serial_2]);
+        assert_eq!(
+            store.load("rpz.example", 60, serial_1).unwrap().unwrap(),
+            zone(
+                serial_1,
+                &["*.a.example", "*.b.example", "a.example", "b.example"]
+            )
+        );
+    }
+
+    #[test]
+    fn publish_includes_pattern_wildcards_and_ip_triggers() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let store = SnapshotStore::new(directory.path(), 2);
+        let blocklist = Blocklist {
+            patterns: vec![
+                NamePattern::parse("*.tracker.example").unwrap().unwrap(),
+                NamePattern::parse(r"/^ads?\./").unwrap().unwrap(),
+            ],
+            response_ip_networks: vec!["192.0.2.1/32".parse().unwrap()],
+            client_ip_networks: vec!["198.51.100.0/24".parse().unwrap()],
+            ..blocklist(&["ads.example"])
+        };
+
+        // act
+        let serial = store.publish(&blocklist).unwrap().unwrap();
+
+        // assert
+        assert_eq!(
+            store.load("rpz.example", 60, serial).unwrap().unwrap(),
+            zone(
+                serial,
+                &[
+                    "*.ads.example",
+                    "*.tracker.example",
+                    "24.0.100.51.198.rpz-client-ip",
+                    "32.1.2.0.192.rpz-ip",
+                    "ads.example"
+                ]
+            )
+        );
+    }
+}
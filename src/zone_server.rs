@@ -0,0 +1,606 @@
+use crate::{
+    dns::{
+        answer_response, empty_response, notify_message, parse_message, parse_query, soa_serial,
+        transfer_responses, Query, Record, CLASS_IN, OPCODE_NOTIFY, OPCODE_QUERY, RCODE_REFUSED,
+        RCODE_SERVFAIL, TYPE_AXFR, TYPE_IXFR, TYPE_SOA,
+    },
+    dns_server::read_tcp_message,
+    file_system::ZoneTransfer,
+    zone::{SnapshotStore, Zone},
+    Blocklist,
+};
+use ipnet::IpNet;
+use log::{error, info, trace, warn};
+use std::{
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::{Arc, PoisonError, RwLock},
+    time::Duration,
+};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream, UdpSocket},
+    time::{sleep, timeout},
+};
+
+const MAX_UDP_MESSAGE_LENGTH: usize = 4096;
+
+const NOTIFY_TIMEOUT: Duration = Duration::from_secs(2);
+
+const NOTIFY_ATTEMPTS: u32 = 3;
+
+const ZONE_REFRESH_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Time a TCP client may leave a connection idle, or take sending a query, before it is closed
+/// (RFC 7766 section 6.2.3).
+const TCP_IDLE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Primary server for the published zone, answering `SOA`, `AXFR` and `IXFR` queries from
+/// secondaries.
+pub struct ZoneServer {
+    origin: String,
+    ttl: u32,
+    store: SnapshotStore,
+
+    /// Networks of secondaries allowed to transfer the zone
+    allow_transfer: Vec<IpNet>,
+
+    /// Secondaries sent `NOTIFY` once a new serial is loaded
+    notify: Vec<SocketAddr>,
+
+    /// Latest published zone, refreshed from the snapshot store in the background so queries are
+    /// answered without reading snapshots
+    zone: RwLock<Option<Arc<Zone>>>,
+}
+
+/// Serial the secondary holds, taken from the `SOA` in the authority section of an `IXFR` query.
+fn ixfr_client_serial(message: &[u8]) -> Option<u32> {
+    let parsed = parse_message(message)?;
+    parsed
+        .authorities
+        .iter()
+        .find_map(|val| soa_serial(message, val))
+}
+
+impl ZoneServer {
+    #[must_use]
+    pub fn new(
+        origin: &str,
+        ttl: u32,
+        store: SnapshotStore,
+        allow_transfer: Vec<IpNet>,
+        notify: Vec<SocketAddr>,
+    ) -> Self {
+        ZoneServer {
+            origin: origin.trim_end_matches('.').to_ascii_lowercase(),
+            ttl,
+            store,
+            allow_transfer,
+            notify,
+            zone: RwLock::new(None),
+        }
+    }
+
+    fn current_zone(&self) -> Option<Arc<Zone>> {
+        self.zone
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Loads the latest snapshot, when its serial differs from the zone being served.  Returns the
+    /// zone, if a new serial was loaded.
+    fn refresh_zone(&self) -> io::Result<Option<Arc<Zone>>> {
+        let Some(serial) = self.store.latest_serial()? else {
+            return Ok(None);
+        };
+        if self.current_zone().is_some_and(|val| val.serial == serial) {
+            return Ok(None);
+        }
+        let Some(zone) = self.store.load(&self.origin, self.ttl, serial)? else {
+            return Ok(None);
+        };
+        info!("Serving zone serial {serial}");
+        let zone = Arc::new(zone);
+        *self.zone.write().unwrap_or_else(PoisonError::into_inner) = Some(Arc::clone(&zone));
+        Ok(Some(zone))
+    }
+
+    /// Refreshes the zone, then notifies secondaries of any new serial.  Secondaries are only
+    /// notified once the serial is served, so the `SOA` query they answer with sees it.
+    async fn refresh_zone_blocking(self: &Arc<Self>) {
+        let server = Arc::clone(self);
+        let result = tokio::task::spawn_blocking(move || server.refresh_zone())
+            .await
+            .map_err(io::Error::other);
+        match result.and_then(|val| val) {
+            Ok(Some(zone)) if !self.notify.is_empty() => {
+                let server = Arc::clone(self);
+                tokio::spawn(async move { notify_secondaries(&zone, &server.notify).await });
+            }
+            Ok(_) => {}
+            Err(error) => error!("Unable to load zone snapshot: {error}"),
+        }
+    }
+
+    /// Picks up snapshots published by builds since the last refresh.
+    async fn refresh_zone_periodically(self: Arc<Self>) -> io::Result<()> {
+        loop {
+            sleep(ZONE_REFRESH_INTERVAL).await;
+            self.refresh_zone_blocking().await;
+        }
+    }
+
+    fn transfer_allowed(&self, peer: IpAddr) -> bool {
+        self.allow_transfer.iter().any(|val| val.contains(&peer))
+    }
+
+    /// Records answering an `IXFR` query.  Falls back to the full zone when no snapshot is kept for
+    /// the secondary's serial, as allowed by RFC 1995 section 4.
+    fn ixfr_records(&self, zone: &Zone, client_serial: Option<u32>) -> io::Result<Vec<Record>> {
+        let Some(client_serial) = client_serial else {
+            return Ok(zone.axfr_records());
+        };
+        if client_serial == zone.serial {
+            return Ok(vec![zone.soa()]);
+        }
+        match self.store.load(&self.origin, self.ttl, client_serial)? {
+            Some(previous) => Ok(zone.ixfr_records(&previous)),
+            None => Ok(zone.axfr_records()),
+        }
+    }
+
+    /// Response messages for a query from `peer`; transfers may span several messages over TCP.
+    fn responses(&self, message: &[u8], tcp: bool, peer: IpAddr) -> Vec<Vec<u8>> {
+        let Some(query) = parse_query(message) else {
+            return Vec::new();
+        };
+        if query.header.opcode() != OPCODE_QUERY
+            || query.question.class != CLASS_IN
+            || query.question.name != self.origin
+        {
+            return vec![empty_response(&query, RCODE_REFUSED)];
+        }
+        let transfer = tcp && matches!(query.question.record_type, TYPE_AXFR | TYPE_IXFR);
+        if transfer && !self.transfer_allowed(peer) {
+            warn!("Refused zone transfer to {peer}, which is not in `allow_transfer`");
+            return vec![empty_response(&query, RCODE_REFUSED)];
+        }
+        let Some(zone) = self.current_zone() else {
+            warn!("No zone published yet, unable to answer query");
+            return vec![empty_response(&query, RCODE_SERVFAIL)];
+        };
+        match query.question.record_type {
+            TYPE_AXFR if tcp => {
+                info!("AXFR of serial {} to {peer}", zone.serial);
+                transfer_responses(&query, &zone.axfr_records())
+            }
+            TYPE_IXFR if tcp => self.ixfr_responses(&query, message, &zone),
+            // over UDP, a lone SOA tells the secondary to retry IXFR over TCP (RFC 1995 section 2)
+            TYPE_SOA | TYPE_IXFR => vec![answer_response(&query, &[zone.soa()])],
+            _ => vec![empty_response(&query, RCODE_REFUSED)],
+        }
+    }
+
+    fn ixfr_responses(&self, query: &Query, message: &[u8], zone: &Zone) -> Vec<Vec<u8>> {
+        let client_serial = ixfr_client_serial(message);
+        info!(
+            "IXFR from serial {} to {}",
+            client_serial.map_or_else(|| String::from("unknown"), |val| val.to_string()),
+            zone.serial
+        );
+        match self.ixfr_records(zone, client_serial) {
+            Ok(value) => transfer_responses(query, &value),
+            Err(error) => {
+                error!("Unable to load zone snapshot: {error}");
+                vec![empty_response(query, RCODE_SERVFAIL)]
+            }
+        }
+    }
+
+    async fn handle_tcp_connection(
+        self: Arc<Self>,
+        mut stream: TcpStream,
+        peer: IpAddr,
+    ) -> io::Result<()> {
+        loop {
+            let message = match timeout(TCP_IDLE_TIMEOUT, read_tcp_message(&mut stream)).await {
+                Ok(Ok(value)) => value,
+                Ok(Err(error)) if error.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Ok(Err(error)) => return Err(error),
+                Err(_) => {
+                    trace!("Closing idle TCP connection from {peer}");
+                    return Ok(());
+                }
+            };
+            let server = Arc::clone(&self);
+            // IXFR may read an older snapshot
+            let responses =
+                tokio::task::spawn_blocking(move || server.responses(&message, true, peer))
+                    .await
+                    .map_err(io::Error::other)?;
+            for response in responses {
+                let length = u16::try_from(response.len())
+                    .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Response too long"))?;
+                stream.write_all(&length.to_be_bytes()).await?;
+                stream.write_all(&response).await?;
+            }
+        }
+    }
+
+    async fn serve_udp(self: Arc<Self>, socket: UdpSocket) -> io::Result<()> {
+        let mut buffer = vec![0u8; MAX_UDP_MESSAGE_LENGTH];
+        loop {
+            let (length, peer) = socket.recv_from(&mut buffer).await?;
+            for response in self.responses(&buffer[..length], false, peer.ip()) {
+                if let Err(error) = socket.send_to(&response, peer).await {
+                    error!("Error sending response to {peer}: {error}");
+                }
+            }
+        }
+    }
+
+    async fn serve_tcp(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, peer) = listener.accept().await?;
+            let server = Arc::clone(&self);
+            tokio::spawn(async move {
+                if let Err(error) = server.handle_tcp_connection(stream, peer.ip()).await {
+                    trace!("Closed TCP connection from {peer}: {error}");
+                }
+            });
+        }
+    }
+
+    /// Answers queries on the UDP socket and TCP listener until either fails.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if receiving on the UDP socket or accepting a TCP connection fails.
+    pub async fn run(self, udp_socket: UdpSocket, tcp_listener: TcpListener) -> io::Result<()> {
+        if let Ok(value) = tcp_listener.local_addr() {
+            info!("Serving zone `{}` on {value}", self.origin);
+        }
+        let server = Arc::new(self);
+        server.refresh_zone_blocking().await;
+        tokio::try_join!(
+            Arc::clone(&server).refresh_zone_periodically(),
+            Arc::clone(&server).serve_udp(udp_socket),
+            server.serve_tcp(tcp_listener)
+        )?;
+        Ok(())
+    }
+}
+
+async fn notify_secondary(secondary: SocketAddr, soa: &Record) -> io::Result<()> {
+    let bind_address: SocketAddr = if secondary.is_ipv4() {
+        (Ipv4Addr::UNSPECIFIED, 0).into()
+    } else {
+        (Ipv6Addr::UNSPECIFIED, 0).into()
+    };
+    let socket = UdpSocket::bind(bind_address).await?;
+    socket.connect(secondary).await?;
+    let id: u16 = rand::random();
+    let message = notify_message(id, soa);
+    let mut buffer = vec![0u8; MAX_UDP_MESSAGE_LENGTH];
+    for _ in 0..NOTIFY_ATTEMPTS {
+        socket.send(&message).await?;
+        let Ok(received) = timeout(NOTIFY_TIMEOUT, socket.recv(&mut buffer)).await else {
+            continue;
+        };
+        let length = received?;
+        if let Some(response) = parse_message(&buffer[..length]) {
+            if response.header.id == id
+                && response.header.is_response()
+                && response.header.opcode() == OPCODE_NOTIFY
+            {
+                return Ok(());
+            }
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::TimedOut,
+        "No response to NOTIFY",
+    ))
+}
+
+/// Sends `NOTIFY` for the zone to each secondary, so they transfer the new serial without waiting
+/// for their refresh timer.  Failures are logged, since secondaries still refresh eventually.
+pub async fn notify_secondaries(zone: &Zone, secondaries: &[SocketAddr]) {
+    let soa = zone.soa();
+    for secondary in secondaries {
+        match notify_secondary(*secondary, &soa).await {
+            Ok(()) => info!("Notified {secondary} of serial {}", zone.serial),
+            Err(error) => warn!(
+                "Unable to notify {secondary} of serial {}: {error}",
+                zone.serial
+            ),
+        }
+    }
+}
+
+/// Stores a snapshot of the blocklist's RPZ records as a new zone serial, when they have changed.
+/// `serve-zone` picks up the new serial, then notifies secondaries.
+///
+/// # Errors
+///
+/// Returns an error if the snapshot cannot be read or stored.
+pub fn publish_zone(zone_transfer: &ZoneTransfer, blocklist: &Blocklist) -> io::Result<()> {
+    let ZoneTransfer {
+        snapshot_directory,
+        keep_snapshots,
+        ..
+    } = zone_transfer;
+    let store = SnapshotStore::new(snapshot_directory, *keep_snapshots);
+    store.publish(blocklist)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{notify_secondaries, ZoneServer};
+    use crate::{
+        dns::{
+            encode_name, parse_message, soa_serial, OPCODE_NOTIFY, RCODE_REFUSED, TYPE_AXFR,
+            TYPE_CNAME, TYPE_IXFR, TYPE_SOA,
+        },
+        zone::{SnapshotStore, Zone},
+        Blocklist,
+    };
+    use std::{net::SocketAddr, time::Duration};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream, UdpSocket},
+        time::timeout,
+    };
+    use url::Host;
+
+    fn query_message(id: u16, record_type: u16, client_serial: Option<u32>) -> Vec<u8> {
+        let mut result = Vec::new();
+        result.extend_from_slice(&id.to_be_bytes());
+        let authority_count = u8::from(client_serial.is_some());
+        result.extend_from_slice(&[0, 0, 0, 1, 0, 0, 0, authority_count, 0, 0]);
+        encode_name("rpz.example", &mut result);
+        result.extend_from_slice(&record_type.to_be_bytes());
+        result.extend_from_slice(&[0, 1]);
+        if let Some(serial) = client_serial {
+            let zone = Zone {
+                origin: String::from("rpz.example"),
+                serial,
+                ttl: 60,
+                owners: Vec::new(),
+            };
+            zone.soa().encode(&mut result);
+        }
+        result
+    }
+
+    async fn start_server(
+        store: SnapshotStore,
+        allow_transfer: &[&str],
+        notify: Vec<SocketAddr>,
+    ) -> SocketAddr {
+        let allow_transfer = allow_transfer
+            .iter()
+            .map(|val| val.parse().unwrap())
+            .collect();
+        let server = ZoneServer::new("rpz.example.", 60, store, allow_transfer, notify);
+        let udp_socket = UdpSocket::bind("127.0.0.1:0").await.unwrap(); // DevSkim: ignore DS162092 - use of local host IP is in test
+        let address = udp_socket.local_addr().unwrap();
+        let tcp_listener = TcpListener::bind(address).await.unwrap();
+        tokio::spawn(server.run(udp_socket, tcp_listener));
+        address
+    }
+
+    /// Sends a transfer query over TCP, reading messages until the closing `SOA`, and returns the
+    /// answer records as `(type, owner name, serial)`.
+    async fn transfer(server: SocketAddr, message: &[u8]) -> Vec<(u16, String, Option<u32>)> {
+        let mut stream = TcpStream::connect(server).await.unwrap();
+        let length = u16::try_from(message.len()).unwrap();
+        stream.write_all(&length.to_be_bytes()).await.unwrap();
+        stream.write_all(message).await.unwrap();
+        let mut result = Vec::new();
+        let mut soa_count = 0;
+        while soa_count < 2 {
+            let length = timeout(Duration::from_secs(5), stream.read_u16())
+                .await
+                .unwrap()
+                .unwrap();
+            let mut buffer = vec![0u8; usize::from(length)];
+            stream.read_exact(&mut buffer).await.unwrap();
+            let response = parse_message(&buffer).unwrap();
+            for record in &response.answers {
+                let serial = soa_serial(&buffer, record);
+                if serial.is_some() {
+                    soa_count += 1;
+                }
+                result.push((record.record_type, record.name.clone(), serial));
+            }
+            // an up to date secondary gets a single SOA
+            if result.len() == 1 {
+                break;
+            }
+        }
+        result
+    }
+
+    #[tokio::test]
+    async fn zone_server_answers_axfr_and_ixfr_over_tcp() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let store = SnapshotStore::new(directory.path(), 5);
+        let serial_0 = store
+            .publish(&Blocklist {
+                hosts: vec![Host::parse("a.example").unwrap()],
+                ..Blocklist::default()
+            })
+            .unwrap()
+            .unwrap();
+        let serial_1 = store
+            .publish(&Blocklist {
+                hosts: vec![Host::parse("b.example").unwrap()],
+                ..Blocklist::default()
+            })
+            .unwrap()
+            .unwrap();
+        let server = start_server(
+            SnapshotStore::new(directory.path(), 5),
+            &["127.0.0.1/32"],
+            Vec::new(),
+        )
+        .await;
+
+        // act
+        let result_0 = transfer(server, &query_message(1, TYPE_AXFR, None)).await;
+        let result_1 = transfer(server, &query_message(2, TYPE_IXFR, Some(serial_0))).await;
+        let result_2 = transfer(server, &query_message(3, TYPE_IXFR, Some(serial_1))).await;
+
+        // assert
+        assert_eq!(result_0.len(), 5);
+        assert_eq!(result_0[0].2, Some(serial_1));
+        assert_eq!(
+            result_0[2],
+            (TYPE_CNAME, String::from("*.b.example.rpz.example"), None)
+        );
+        assert_eq!(result_0[4].2, Some(serial_1));
+        let serials: Vec<Option<u32>> = result_1.iter().map(|val| val.2).collect();
+        assert_eq!(
+            serials,
+            vec![
+                Some(serial_1),
+                Some(serial_0),
+                None,
+                None,
+                Some(serial_1),
+                None,
+                None,
+                Some(serial_1)
+            ]
+        );
+        assert_eq!(result_1[2].1, "*.a.example.rpz.example");
+        assert_eq!(result_1[5].1, "*.b.example.rpz.example");
+        assert_eq!(
+            result_2,
+            vec![(TYPE_SOA, String::from("rpz.example"), Some(serial_1))]
+        );
+    }
+
+    #[tokio::test]
+    async fn zone_server_refuses_transfers_outside_allowed_networks() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let store = SnapshotStore::new(directory.path(), 5);
+        store
+            .publish(&Blocklist {
+                hosts: vec![Host::parse("a.example").unwrap()],
+                ..Blocklist::default()
+            })
+            .unwrap()
+            .unwrap();
+        let server = start_server(
+            SnapshotStore::new(directory.path(), 5),
+            &["192.0.2.0/24"],
+            Vec::new(),
+        )
+        .await;
+        let message = query_message(1, TYPE_AXFR, None);
+
+        // act
+        let mut stream = TcpStream::connect(server).await.unwrap();
+        let length = u16::try_from(message.len()).unwrap();
+        stream.write_all(&length.to_be_bytes()).await.unwrap();
+        stream.write_all(&message).await.unwrap();
+        let length = timeout(Duration::from_secs(5), stream.read_u16())
+            .await
+            .unwrap()
+            .unwrap();
+        let mut buffer = vec![0u8; usize::from(length)];
+        stream.read_exact(&mut buffer).await.unwrap();
+
+        // assert
+        let response = parse_message(&buffer).unwrap();
+        assert_eq!(response.header.flags & 0xf, u16::from(RCODE_REFUSED));
+        assert!(response.answers.is_empty());
+    }
+
+    #[tokio::test]
+    async fn notify_secondaries_sends_notify_with_current_soa() {
+        // arrange
+        let secondary = UdpSocket::bind("127.0.0.1:0").await.unwrap(); // DevSkim: ignore DS162092 - use of local host IP is in test
+        let secondary_address = secondary.local_addr().unwrap();
+        let received = tokio::spawn(async move {
+            let mut buffer = [0u8; 512];
+            let (length, peer) = secondary.recv_from(&mut buffer).await.unwrap();
+            let mut response = buffer[..length].to_vec();
+            response[2] |= 0x80;
+            secondary.send_to(&response, peer).await.unwrap();
+            buffer[..length].to_vec()
+        });
+        let zone = Zone {
+            origin: String::from("rpz.example"),
+            serial: 42,
+            ttl: 60,
+            owners: Vec::new(),
+        };
+
+        // act
+        notify_secondaries(&zone, &[secondary_address]).await;
+
+        // assert
+        let message = received.await.unwrap();
+        let notify = parse_message(&message).unwrap();
+        assert_eq!(notify.header.opcode(), OPCODE_NOTIFY);
+        assert_eq!(soa_serial(&message, &notify.answers[0]), Some(42));
+    }
+
+    #[tokio::test]
+    async fn zone_server_notifies_secondaries_once_serving_new_serial() {
+        // arrange
+        let directory = tempfile::tempdir().unwrap();
+        let store = SnapshotStore::new(directory.path(), 5);
+        let serial = store
+            .publish(&Blocklist {
+                hosts: vec![Host::parse("a.example").unwrap()],
+                ..Blocklist::default()
+            })
+            .unwrap()
+            .unwrap();
+        let secondary = UdpSocket::bind("127.0.0.1:0").await.unwrap(); // DevSkim: ignore DS162092 - use of local host IP is in test
+        let secondary_address = secondary.local_addr().unwrap();
+
+        // act
+        let server = start_server(
+            SnapshotStore::new(directory.path(), 5),
+            &["127.0.0.1/32"],
+            vec![secondary_address],
+        )
+        .await;
+        let mut buffer = [0u8; 512];
+        let (length, _) = timeout(Duration::from_secs(5), secondary.recv_from(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+        let notify = buffer[..length].to_vec();
+        secondary
+            .send_to(&query_message(7, TYPE_SOA, None), server)
+            .await
+            .unwrap();
+        let (length, _) = timeout(Duration::from_secs(5), secondary.recv_from(&mut buffer))
+            .await
+            .unwrap()
+            .unwrap();
+
+        // assert
+        let notify_message = parse_message(&notify).unwrap();
+        assert_eq!(notify_message.header.opcode(), OPCODE_NOTIFY);
+        assert_eq!(
+            soa_serial(&notify, &notify_message.answers[0]),
+            Some(serial)
+        );
+        let response = parse_message(&buffer[..length]).unwrap();
+        assert_eq!(
+            soa_serial(&buffer[..length], &response.answers[0]),
+            Some(serial)
+        );
+    }
+}